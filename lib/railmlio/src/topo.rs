@@ -18,6 +18,8 @@ pub struct Topological {
     pub tracks :Vec<TopoTrack>,
     pub nodes :Vec<TopoNode>,
     pub connections :Vec<TopoConnection>,
+    /// Maximum speed (km/h) on the deviating branch, by switch node index.
+    pub switch_speeds :HashMap<usize, f64>,
 }
 
 #[derive(Debug)]
@@ -129,6 +131,7 @@ pub struct TopoSwitchInfo {
     switch_geometry :Side,
     dir :AB,
     pos :f64,
+    max_speed :Option<f64>,
 }
 
 pub fn switch_info(sw :Switch) -> Result<TopoSwitchInfo,TopoConvErr> {
@@ -153,6 +156,7 @@ pub fn switch_info(sw :Switch) -> Result<TopoSwitchInfo,TopoConvErr> {
                             deviating_side: deviating_side,
                             switch_geometry: switch_geometry,
                             pos: pos.offset,
+                            max_speed: connection.max_speed,
                             dir: match connection.orientation { 
                                 ConnectionOrientation::Outgoing => AB::A,
                                 ConnectionOrientation::Incoming => AB::B,
@@ -174,6 +178,7 @@ pub fn convert_railml_topo(doc :RailML) -> Result<Topological,TopoConvErr> {
         tracks: Vec::new(),
         nodes :Vec::new(),
         connections: Vec::new(),
+        switch_speeds: HashMap::new(),
     };
 
     let mut named_track_ports :HashMap<(String,String), (usize, AB)> = HashMap::new();
@@ -200,6 +205,7 @@ pub fn convert_railml_topo(doc :RailML) -> Result<Topological,TopoConvErr> {
                 topo.tracks[track_idx].length = sw_info.pos - current_offset;
 
                 let nd = new_node(&mut topo, TopoNode::Switch(sw_info.switch_geometry));
+                if let Some(v) = sw_info.max_speed { topo.switch_speeds.insert(nd, v); }
                 named_node_ports.insert(sw_info.connref, (nd, sw_info.deviating_side.to_port()));
                 let (mut a_port, mut b_port) = (Port::Trunk, sw_info.deviating_side.opposite().to_port());
                 if sw_info.dir == AB::B { std::mem::swap(&mut a_port, &mut b_port); }
@@ -16,21 +16,33 @@ pub enum ConvertRouteError {
 }
 
 pub struct Config {
-    // TODO what can we put in here
-    //
-    // 0. section overlap minimum length
-    // 1. route minimum length
-    // ---
-    // 2. overlap policy
-
-    section_tolerance :f64,
-    route_minimum_length: f64,
+    /// Sections overlapping the route path by less than this length
+    /// are not included in the route's resources.
+    pub section_tolerance :f64,
+    /// Paths shorter than this length are reported as an issue instead
+    /// of producing a route.
+    pub route_minimum_length: f64,
+    /// Length of the overlap past the exit signal. Detection sections
+    /// entered within this length are part of the overlap. Facing switches
+    /// inside the overlap give alternative overlaps. Zero means no overlap.
+    pub overlap_length: f64,
+    /// Time after which the overlap is released when the train has
+    /// stopped at the exit signal. `None` means the overlap is kept until
+    /// the next route is set.
+    pub overlap_timeout: Option<f64>,
+    /// Lock switches adjacent to the route path in a position leading
+    /// away from the route (flank protection). Off by default, as the
+    /// extra locked switches change which routes can be set together.
+    pub flank_protection: bool,
 }
 
 impl Default for Config {
     fn default() -> Config { Config {
         section_tolerance: 15.0,
         route_minimum_length: 15.0,
+        overlap_length: 0.0,
+        overlap_timeout: None,
+        flank_protection: false,
     } }
 }
 
@@ -53,7 +65,20 @@ pub struct Path {
 
 pub type RoutePath = Vec<(NodeId,NodeId)>;
 
-pub fn find_routes(config :Config, model :&StaticInfrastructure) -> Result<(Vec<(Route,RoutePath)>, Vec<ConvertRouteIssue>), ConvertRouteError> {
+/// Elements protecting a route from movements coming from the
+/// branches of the switches on the route path.
+#[derive(Debug, Clone, Default)]
+pub struct FlankProtection {
+    /// Switches locked in a position leading away from the route.
+    /// These are also added to the route's resources.
+    pub switches: SmallVec<[(ObjectId, SwitchPosition); 2]>,
+    /// Signals facing towards the route, which must show stop.
+    /// The simulator has no signal resources, so these are not locked
+    /// by the route and are only reported for display and route tables.
+    pub signals: SmallVec<[ObjectId; 2]>,
+}
+
+pub fn find_routes(config :Config, model :&StaticInfrastructure) -> Result<(Vec<(Route,RoutePath,FlankProtection)>, Vec<ConvertRouteIssue>), ConvertRouteError> {
 
     let mut routes = Vec::new();
     let mut issues = Vec::new();
@@ -99,7 +124,12 @@ pub fn find_routes(config :Config, model :&StaticInfrastructure) -> Result<(Vec<
                             StaticObject::Signal { .. } if curr_state.node != entry.node => {
                                 let exit = RouteEntryExit::Signal(*obj_idx);
                                 match make_route(&config, &curr_state, entry.entry, exit) {
-                                    Ok(route) => routes.push((route, curr_state.edges_taken.clone())),
+                                    Ok(mut route) => {
+                                        route.overlaps = find_overlaps(&config, model, &curr_state)?;
+                                        route.swinging_overlap = route.overlaps.len() > 1;
+                                        let flank = add_flank_protection(&config, model, &mut route)?;
+                                        routes.push((route, curr_state.edges_taken.clone(), flank));
+                                    },
                                     Err(err) => issues.push(err),
                                 }
 
@@ -138,7 +168,10 @@ pub fn find_routes(config :Config, model :&StaticInfrastructure) -> Result<(Vec<
                         Edges::ModelBoundary => {
                             let exit = RouteEntryExit::Boundary(Some(curr_state.node));
                             match make_route(&config, &curr_state, entry.entry, exit) {
-                                Ok(route) => routes.push((route, curr_state.edges_taken.clone())),
+                                Ok(mut route) => {
+                                    let flank = add_flank_protection(&config, model, &mut route)?;
+                                    routes.push((route, curr_state.edges_taken.clone(), flank));
+                                },
                                 Err(err) => issues.push(err),
                             }
                             break;
@@ -280,3 +313,321 @@ pub fn make_route(config: &Config, state :&Path, entry :RouteEntryExit, exit: Ro
     })
 }
 
+#[derive(Debug, Clone)]
+struct OverlapPath {
+    node: NodeId,
+    length: f64,
+    sections: SmallVec<[ObjectId; 4]>,
+    switches: SmallVec<[(ObjectId, SwitchPosition); 4]>,
+}
+
+/// Find the overlaps of a route ending at the exit signal in `state`.
+/// The search follows the track past the signal for `config.overlap_length`,
+/// branching at facing switches, so that each path gives one alternative.
+pub fn find_overlaps(config :&Config, model :&StaticInfrastructure, state :&Path) 
+        -> Result<SmallVec<[Overlap; 1]>, ConvertRouteError> {
+
+    let mut overlaps : SmallVec<[Overlap; 1]> = SmallVec::new();
+    if !(config.overlap_length > 0.0) { return Ok(overlaps); }
+
+    let mut search_stack = vec![OverlapPath {
+        node: state.node,
+        length: 0.0,
+        sections: SmallVec::new(),
+        switches: SmallVec::new(),
+    }];
+
+    while let Some(mut curr_state) = search_stack.pop() {
+        // A path which branches at a facing switch continues as one path per branch.
+        let mut branched = false;
+        loop {
+            if curr_state.length >= config.overlap_length { break; }
+
+            for obj_idx in model.nodes[curr_state.node].objects.iter() {
+                if let StaticObject::TVDLimit { enter: Some(s), .. } = &model.objects[*obj_idx] {
+                    // Sections that the route itself has entered are already allocated.
+                    let in_route = state.entered_sections.iter().any(|(x,_)| x == s);
+                    if !in_route && !curr_state.sections.contains(s) {
+                        curr_state.sections.push(*s);
+                    }
+                }
+            }
+
+            match model.nodes[curr_state.node].edges {
+                Edges::Nothing | Edges::ModelBoundary => { break; },
+                Edges::Single(other, d) => {
+                    if let Edges::Switchable(sw) = model.nodes[other].edges {
+                        if let Some(StaticObject::Switch { left_link, right_link, .. }) = model.objects.get(sw) {
+                            let pos = if left_link.0 == curr_state.node { SwitchPosition::Left } 
+                                        else if right_link.0 == curr_state.node { SwitchPosition::Right }
+                                        else {
+                                            return Err(ConvertRouteError::String(format!("Switch misconfigured {}", sw))); };
+                            curr_state.switches.push((sw,pos));
+                        } else {
+                            return Err(ConvertRouteError::String(format!("Switch misconfigured {}", sw)));
+                        }
+                    }
+                    curr_state.node = model.nodes[other].other_node;
+                    curr_state.length += d;
+                },
+                Edges::Switchable(sw) => {
+                    if let Some(StaticObject::Switch { left_link, right_link, .. }) = model.objects.get(sw) {
+                        for (pos,(node,d)) in &[(SwitchPosition::Left, *left_link), 
+                                                (SwitchPosition::Right, *right_link)] {
+                            let mut next_state = curr_state.clone();
+                            next_state.switches.push((sw, *pos));
+                            next_state.node = model.nodes[*node].other_node;
+                            next_state.length += d;
+                            search_stack.push(next_state);
+                        }
+                        branched = true;
+                        break;
+                    } else {
+                        return Err(ConvertRouteError::String(format!("Switch misconfigured {}", sw)));
+                    }
+                },
+            }
+        }
+
+        if !branched && (curr_state.sections.len() > 0 || curr_state.switches.len() > 0) {
+            overlaps.push(Overlap {
+                name: Some(format!("{}", overlaps.len())),
+                sections: curr_state.sections,
+                switch_positions: curr_state.switches,
+                timeout: config.overlap_timeout,
+            });
+        }
+    }
+
+    Ok(overlaps)
+}
+
+/// Find flank protection for the switches on the route path, and add the
+/// protecting switches to the route's resources. A protecting switch is
+/// released together with the path switch it protects.
+pub fn add_flank_protection(config :&Config, model :&StaticInfrastructure, route :&mut Route) 
+        -> Result<FlankProtection, ConvertRouteError> {
+
+    let mut flank = FlankProtection::default();
+    if !config.flank_protection { return Ok(flank); }
+
+    let path_switches = route.resources.switch_positions.clone();
+    for (sw,pos) in path_switches.iter() {
+        let (left_link, right_link) = match model.objects.get(*sw) {
+            Some(StaticObject::Switch { left_link, right_link, .. }) => (*left_link, *right_link),
+            _ => { return Err(ConvertRouteError::String(format!("Switch misconfigured {}", sw))); },
+        };
+        let branch = match pos {
+            SwitchPosition::Left => right_link,
+            SwitchPosition::Right => left_link,
+        };
+
+        let (switches, signals) = find_flank(model, branch.0)?;
+        for (flank_sw, flank_pos) in switches {
+            if route.resources.switch_positions.iter().any(|(x,_)| *x == flank_sw) { continue; }
+            route.resources.switch_positions.push((flank_sw, flank_pos));
+            flank.switches.push((flank_sw, flank_pos));
+            for release in route.resources.releases.iter_mut() {
+                if release.resources.contains(sw) {
+                    release.resources.push(flank_sw);
+                }
+            }
+        }
+        for signal in signals {
+            if !flank.signals.contains(&signal) { flank.signals.push(signal); }
+        }
+    }
+
+    Ok(flank)
+}
+
+/// Search away from a switch along its unused branch, starting at the
+/// (backward-facing) `branch_node`. The search stops at the first signal
+/// facing towards the switch, or at the first switch which can be set to
+/// lead away from it. Facing switches are searched through on both branches.
+fn find_flank(model :&StaticInfrastructure, branch_node :NodeId) 
+        -> Result<(Vec<(ObjectId,SwitchPosition)>, Vec<ObjectId>), ConvertRouteError> {
+    let mut switches = Vec::new();
+    let mut signals = Vec::new();
+    let mut visited = HashSet::new();
+    let mut search_stack = vec![model.nodes[branch_node].other_node];
+
+    while let Some(mut node) = search_stack.pop() {
+        loop {
+            if !visited.insert(node) { break; }
+
+            // Signals for movements towards the route are on the opposite node.
+            let signal = model.nodes[model.nodes[node].other_node].objects.iter()
+                .find(|o| if let StaticObject::Signal { .. } = model.objects[**o] { true } else { false });
+            if let Some(signal) = signal {
+                signals.push(*signal);
+                break;
+            }
+
+            match model.nodes[node].edges {
+                Edges::Nothing | Edges::ModelBoundary => { break; },
+                Edges::Single(other, _) => {
+                    if let Edges::Switchable(sw) = model.nodes[other].edges {
+                        // Trailing switch: set it to the other branch.
+                        if let Some(StaticObject::Switch { left_link, right_link, .. }) = model.objects.get(sw) {
+                            let pos = if left_link.0 == node { SwitchPosition::Right } 
+                                        else if right_link.0 == node { SwitchPosition::Left }
+                                        else {
+                                            return Err(ConvertRouteError::String(format!("Switch misconfigured {}", sw))); };
+                            switches.push((sw, pos));
+                            break;
+                        } else {
+                            return Err(ConvertRouteError::String(format!("Switch misconfigured {}", sw)));
+                        }
+                    }
+                    node = model.nodes[other].other_node;
+                },
+                Edges::Switchable(sw) => {
+                    if let Some(StaticObject::Switch { left_link, right_link, .. }) = model.objects.get(sw) {
+                        search_stack.push(model.nodes[left_link.0].other_node);
+                        search_stack.push(model.nodes[right_link.0].other_node);
+                        break;
+                    } else {
+                        return Err(ConvertRouteError::String(format!("Switch misconfigured {}", sw)));
+                    }
+                },
+            }
+        }
+    }
+
+    Ok((switches, signals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(other_node :NodeId, edges :Edges, objects :Vec<ObjectId>) -> Node {
+        Node { other_node, edges, objects: objects.into_iter().collect() }
+    }
+
+    /// Boundary -- signal -- detector -- facing switch -- two boundaries.
+    fn facing_switch_after_signal() -> StaticInfrastructure {
+        StaticInfrastructure {
+            objects: vec![
+                StaticObject::Signal { has_distant: false },
+                StaticObject::TVDSection,
+                StaticObject::TVDLimit { enter: Some(1), exit: None },
+                StaticObject::Switch { left_link: (8, 0.0), right_link: (10, 0.0),
+                                       branch_side: SwitchPosition::Left },
+            ],
+            nodes: vec![
+                node(1, Edges::ModelBoundary, vec![]),
+                node(0, Edges::Single(2, 100.0), vec![]),
+                node(3, Edges::Single(1, 100.0), vec![]),
+                node(2, Edges::Single(4, 100.0), vec![0]),
+                node(5, Edges::Single(3, 100.0), vec![]),
+                node(4, Edges::Single(6, 50.0), vec![2]),
+                node(7, Edges::Single(5, 50.0), vec![]),
+                node(6, Edges::Switchable(3), vec![]),
+                node(9, Edges::Single(7, 0.0), vec![]),
+                node(8, Edges::Single(12, 100.0), vec![]),
+                node(11, Edges::Single(7, 0.0), vec![]),
+                node(10, Edges::Single(14, 100.0), vec![]),
+                node(13, Edges::Single(9, 100.0), vec![]),
+                node(12, Edges::ModelBoundary, vec![]),
+                node(15, Edges::Single(11, 100.0), vec![]),
+                node(14, Edges::ModelBoundary, vec![]),
+            ],
+        }
+    }
+
+    #[test]
+    pub fn overlap_through_facing_switch() {
+        let config = Config { overlap_length: 200.0, ..Default::default() };
+        let (routes, _issues) = find_routes(config, &facing_switch_after_signal()).unwrap();
+        let (route,_,_) = routes.iter()
+            .find(|(r,_,_)| if let RouteEntryExit::Signal(0) = r.exit { true } else { false })
+            .unwrap();
+
+        assert_eq!(route.overlaps.len(), 2);
+        assert!(route.swinging_overlap);
+        for (overlap, pos) in route.overlaps.iter().zip([SwitchPosition::Right, SwitchPosition::Left].iter()) {
+            assert_eq!(overlap.sections.iter().cloned().collect::<Vec<_>>(), vec![1]);
+            assert_eq!(overlap.switch_positions.iter().cloned().collect::<Vec<_>>(), vec![(3, *pos)]);
+        }
+    }
+
+    #[test]
+    pub fn no_overlap_by_default() {
+        let (routes, _issues) = find_routes(Config::default(), &facing_switch_after_signal()).unwrap();
+        assert!(routes.iter().all(|(r,_,_)| r.overlaps.is_empty() && !r.swinging_overlap));
+    }
+
+    /// Signal -- facing switch with a boundary on the left branch. The right
+    /// branch has a signal facing away from the switch, and leads into the left
+    /// branch of a trailing switch. The trailing switch is the flank protection
+    /// of the facing switch's left position.
+    ///
+    ///   A --- S0 --- SW1 --------------------- B
+    ///                   \
+    ///                    S3 --- SW2 --- D
+    ///                          /
+    ///                  C ------
+    fn flank_switch() -> StaticInfrastructure {
+        StaticInfrastructure {
+            objects: vec![
+                StaticObject::Signal { has_distant: false },
+                StaticObject::Switch { left_link: (6, 100.0), right_link: (8, 100.0),
+                                       branch_side: SwitchPosition::Right },
+                StaticObject::Switch { left_link: (9, 100.0), right_link: (11, 100.0),
+                                       branch_side: SwitchPosition::Left },
+                StaticObject::Signal { has_distant: false },
+            ],
+            nodes: vec![
+                node(1, Edges::ModelBoundary, vec![]),
+                node(0, Edges::Single(2, 100.0), vec![]),
+                node(3, Edges::Single(1, 100.0), vec![]),
+                node(2, Edges::Single(4, 100.0), vec![0]),
+                node(5, Edges::Single(3, 100.0), vec![]),
+                node(4, Edges::Switchable(1), vec![]),
+                node(7, Edges::Single(5, 100.0), vec![]),
+                node(6, Edges::ModelBoundary, vec![]),
+                node(9, Edges::Single(5, 100.0), vec![]),
+                node(8, Edges::Single(12, 100.0), vec![3]),
+                node(11, Edges::ModelBoundary, vec![]),
+                node(10, Edges::Single(12, 100.0), vec![]),
+                node(13, Edges::Switchable(2), vec![]),
+                node(12, Edges::Single(14, 100.0), vec![]),
+                node(15, Edges::Single(13, 100.0), vec![]),
+                node(14, Edges::ModelBoundary, vec![]),
+            ],
+        }
+    }
+
+    fn conflicting_switches(a :&Route, b :&Route) -> bool {
+        a.resources.switch_positions.iter().any(|(sw_a,pos_a)| 
+            b.resources.switch_positions.iter().any(|(sw_b,pos_b)| sw_a == sw_b && pos_a != pos_b))
+    }
+
+    fn signal_to_boundary(routes :&[(Route,RoutePath,FlankProtection)], 
+                          signal :ObjectId, boundary :NodeId) -> &Route {
+        &routes.iter().find(|(r,_,_)| match (r.entry, r.exit) {
+            (RouteEntryExit::Signal(s), RouteEntryExit::Boundary(Some(b))) => s == signal && b == boundary,
+            _ => false,
+        }).unwrap().0
+    }
+
+    #[test]
+    pub fn flank_switch_conflict() {
+        let (routes, _issues) = find_routes(Config::default(), &flank_switch()).unwrap();
+        let (to_b, to_d) = (signal_to_boundary(&routes, 0, 7), signal_to_boundary(&routes, 3, 15));
+        assert_eq!(to_b.resources.switch_positions.iter().cloned().collect::<Vec<_>>(), 
+                   vec![(1, SwitchPosition::Left)]);
+        assert!(!conflicting_switches(to_b, to_d));
+
+        let config = Config { flank_protection: true, ..Default::default() };
+        let (routes, _issues) = find_routes(config, &flank_switch()).unwrap();
+        let (to_b, to_d) = (signal_to_boundary(&routes, 0, 7), signal_to_boundary(&routes, 3, 15));
+        assert_eq!(to_b.resources.switch_positions.iter().cloned().collect::<Vec<_>>(), 
+                   vec![(1, SwitchPosition::Left), (2, SwitchPosition::Right)]);
+        assert_eq!(to_d.resources.switch_positions.iter().cloned().collect::<Vec<_>>(), 
+                   vec![(2, SwitchPosition::Left)]);
+        assert!(conflicting_switches(to_b, to_d));
+    }
+}
@@ -2,6 +2,7 @@ use crate::document::Document;
 use crate::config::Config;
 use crate::gui::windows::logview::LogStore;
 use crate::import;
+use crate::vehiclelib;
 use crate::gui;
 
 pub struct App {
@@ -33,9 +34,15 @@ pub struct Windows {
     pub log: bool,
     pub quit: bool,
     pub vehicles: bool,
+    pub issues: bool,
+    pub settings: bool,
+    pub conflicts: bool,
     pub diagram_split :Option<f32>,
     pub import_window :import::ImportWindow,
     pub synthesis_window :Option<gui::windows::synthesis::SynthesisWindow>,
+    pub templates_window :Option<gui::windows::templates::TemplatesWindow>,
+    /// Loaded when the vehicles window is first opened.
+    pub vehicle_library :Option<vehiclelib::VehicleLibrary>,
 }
 
 impl Windows {
@@ -46,11 +53,16 @@ impl Windows {
             log: false,
             quit: false,
             vehicles: false,
+            issues: false,
+            settings: false,
+            conflicts: false,
 
             diagram_split: None,
 
             import_window: import::ImportWindow::new(bg),
             synthesis_window: None,
+            templates_window: None,
+            vehicle_library: None,
         }
     }
 }
@@ -0,0 +1,235 @@
+// Headless command-line mode.
+//
+// Runs the same analysis pipeline as `Analysis::update`, but synchronously
+// and without opening a window, and prints a JSON summary to stdout.
+// Usage: `junction analyze model.junc`
+//        `junction routetable model.junc [--csv|--md]`
+
+use serde::Serialize;
+use std::sync::atomic::AtomicBool;
+use matches::matches;
+
+use crate::document::model::*;
+use crate::document::dgraph::*;
+use crate::document::topology;
+use crate::document::interlocking;
+use crate::document::history;
+use crate::document::dispatch;
+use crate::document::plan;
+use crate::document::routetable;
+use crate::document::dynamics;
+use crate::file;
+
+#[derive(Serialize, Debug, Default)]
+pub struct Summary {
+    pub file :String,
+    pub ok :bool,
+    pub error :Option<String>,
+    pub issues :Vec<String>,
+    pub topology :Option<TopologySummary>,
+    pub dgraph :Option<DGraphSummary>,
+    pub interlocking :Option<InterlockingSummary>,
+    pub dispatches :Vec<DispatchSummary>,
+    pub plans :Vec<PlanSummary>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TopologySummary {
+    pub tracks :usize,
+    pub nodes :usize,
+    pub error_nodes :usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DGraphSummary {
+    pub nodes :usize,
+    pub objects :usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InterlockingSummary {
+    pub routes :usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DispatchSummary {
+    pub id :usize,
+    pub name :String,
+    pub ok :bool,
+    pub error :Option<String>,
+    pub trains :usize,
+    pub max_time :Option<f64>,
+    /// Capacity consumption of the compressed timetable (percent).
+    pub capacity :Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PlanSummary {
+    pub id :usize,
+    pub name :String,
+    pub ok :bool,
+    pub error :Option<String>,
+    pub dispatches :usize,
+    pub diagnostics :Vec<String>,
+    /// For periodic plans, the shortest cycle time of any dispatch.
+    pub min_cycle_time :Option<f64>,
+}
+
+/// Parse command line arguments. Returns `None` if the arguments do not
+/// ask for a headless command, so that the GUI should be started.
+/// Otherwise, runs the command and returns the process exit code.
+pub fn run(args :&[String]) -> Option<i32> {
+    match args.get(1).map(|x| x.as_str()) {
+        Some("analyze") => {
+            let filename = match args.get(2) {
+                Some(f) => f,
+                None => {
+                    eprintln!("Usage: {} analyze <file>", env!("CARGO_PKG_NAME"));
+                    return Some(2);
+                },
+            };
+            let summary = analyze(filename);
+            match serde_json::to_string_pretty(&summary) {
+                Ok(s) => println!("{}", s),
+                Err(e) => { eprintln!("Could not write summary: {}", e); return Some(2); },
+            }
+            Some(if summary.ok { 0 } else { 1 })
+        },
+        Some("routetable") => {
+            let (filename, csv) = match (args.get(2), args.get(3).map(|x| x.as_str())) {
+                (Some(f), None) | (Some(f), Some("--md")) => (f, false),
+                (Some(f), Some("--csv")) => (f, true),
+                _ => {
+                    eprintln!("Usage: {} routetable <file> [--csv|--md]", env!("CARGO_PKG_NAME"));
+                    return Some(2);
+                },
+            };
+            match route_table(filename) {
+                Ok(rows) => {
+                    print!("{}", if csv { routetable::to_csv(&rows) } 
+                                 else { routetable::to_markdown(&rows) });
+                    Some(0)
+                },
+                Err(e) => { eprintln!("{}", e); Some(1) },
+            }
+        },
+        _ => None,
+    }
+}
+
+pub fn analyze(filename :&str) -> Summary {
+    let mut summary = Summary { file: filename.to_string(), ..Default::default() };
+    match file::load(filename) {
+        Ok(model) => analyze_model(&model, &mut summary),
+        Err(e) => { summary.error = Some(format!("Could not load file: {}", e)); },
+    }
+    summary.ok = summary.error.is_none()
+        && summary.dispatches.iter().all(|d| d.ok)
+        && summary.plans.iter().all(|p| p.ok);
+    summary
+}
+
+pub fn route_table(filename :&str) -> Result<Vec<routetable::RouteTableRow>, String> {
+    let model = file::load(filename).map_err(|e| format!("Could not load file: {}", e))?;
+    let topology = topology::convert(&model, 50.0).map_err(|i| i.message())?;
+    let dgraph = DGraphBuilder::convert(&topology).map_err(|i| i.message())?;
+    let il = interlocking::calc(&dgraph, &model.settings.interlocking).map_err(|i| i.message())?;
+    Ok(routetable::rows(&dgraph, &il))
+}
+
+fn analyze_model(model :&Model, summary :&mut Summary) {
+    let topology = match topology::convert(model, 50.0) {
+        Ok(t) => t,
+        Err(issue) => { summary.error = Some(issue.message()); return; },
+    };
+    summary.issues.extend(topology.issues.iter().map(|i| i.message()));
+    summary.topology = Some(TopologySummary {
+        tracks: topology.tracks.len(),
+        nodes: topology.locations.len(),
+        error_nodes: topology.locations.values()
+            .filter(|(nd,_)| matches!(nd, NDType::Err)).count(),
+    });
+
+    let dgraph = match DGraphBuilder::convert(&topology) {
+        Ok(d) => d,
+        Err(issue) => { summary.error = Some(issue.message()); return; },
+    };
+    summary.issues.extend(dgraph.issues.iter().map(|i| i.message()));
+    summary.dgraph = Some(DGraphSummary {
+        nodes: dgraph.rolling_inf.nodes.len(),
+        objects: dgraph.rolling_inf.objects.len(),
+    });
+
+    let interlocking = match interlocking::calc(&dgraph, &model.settings.interlocking) {
+        Ok(il) => il,
+        Err(issue) => { summary.error = Some(issue.message()); return; },
+    };
+    summary.interlocking = Some(InterlockingSummary { routes: interlocking.routes.len() });
+    summary.issues.extend(interlocking.route_issues.iter().map(|i| i.message()));
+
+    let vehicles = dynamics::vehicles(model);
+    for (i,d) in model.dispatches.iter() {
+        let result = history::get_history(&vehicles, &dgraph,
+                                          &interlocking, &d.commands, &d.trains);
+        summary.dispatches.push(match result {
+            Ok((history,_)) => {
+                let output = dispatch::DispatchOutput::from_history(d.clone(), &dgraph, history);
+                DispatchSummary {
+                    id: *i, name: d.name.clone(), ok: true, error: None,
+                    trains: output.history.trains.len(),
+                    max_time: Some(dispatch::max_time(&output.history)),
+                    capacity: Some(output.capacity(&model.settings.timing, output.line()).percent),
+                }
+            },
+            Err(e) => DispatchSummary {
+                id: *i, name: d.name.clone(), ok: false, error: Some(e),
+                trains: 0, max_time: None, capacity: None,
+            },
+        });
+    }
+
+    for (i,p) in model.plans.iter() {
+        let result = plan::get_dispatches(&dgraph, &interlocking, &vehicles, p, &AtomicBool::new(false));
+        summary.plans.push(match result {
+            Ok((dispatches,diagnostics)) if dispatches.len() > 0 => {
+                let mut lines = diagnostics.lines(p);
+                let min_cycle_time = if p.config.cycle_time.is_some() {
+                    min_cycle_time(&dgraph, &interlocking, &vehicles, &dispatches, &mut lines)
+                } else { None };
+                PlanSummary {
+                    id: *i, name: p.name.clone(), ok: true, error: None,
+                    dispatches: dispatches.len(),
+                    diagnostics: lines,
+                    min_cycle_time,
+                }
+            },
+            Ok((_,diagnostics)) => PlanSummary {
+                id: *i, name: p.name.clone(), ok: false,
+                error: Some(format!("No dispatches satisfy the plan")),
+                dispatches: 0,
+                diagnostics: diagnostics.lines(p),
+                min_cycle_time: None,
+            },
+            Err(e) => PlanSummary {
+                id: *i, name: p.name.clone(), ok: false, error: Some(e),
+                dispatches: 0, diagnostics: Vec::new(), min_cycle_time: None,
+            },
+        });
+    }
+}
+
+/// The shortest cycle time of any of the plan's dispatches. Dispatches
+/// which cannot be repeated, or whose simulation fails, are reported 
+/// in `lines`.
+fn min_cycle_time(dgraph :&DGraph, il :&interlocking::Interlocking, vehicles :&[(usize,Vehicle)],
+                  dispatches :&[(Dispatch, history::History)], lines :&mut Vec<String>) -> Option<f64> {
+    let mut min :Option<f64> = None;
+    for (d,history) in dispatches.iter() {
+        match plan::min_cycle_time(dgraph, il, vehicles, &d.commands, history) {
+            Ok(Some(t)) => { min = Some(min.map(|m| m.min(t)).unwrap_or(t)); },
+            Ok(None) => { lines.push(format!("{} cannot be repeated.", d.name)); },
+            Err(e) => { lines.push(format!("{}: cycle time simulation failed: {}", d.name, e)); },
+        }
+    }
+    min
+}
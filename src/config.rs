@@ -78,6 +78,8 @@ lazy_static! {
                 RailUIColorName::CanvasTVDReserved => const_cstr!("Canvas TVD reserved"),
                 RailUIColorName::CanvasRoutePath => const_cstr!("Canvas route path"),
                 RailUIColorName::CanvasRouteSection => const_cstr!("Canvas route section"),
+                RailUIColorName::CanvasRouteOverlap => const_cstr!("Canvas route overlap"),
+                RailUIColorName::CanvasRouteFlank => const_cstr!("Canvas route flank protection"),
                 RailUIColorName::CanvasSelectionWindow => const_cstr!("Canvas selection window"),
                 RailUIColorName::GraphBackground => const_cstr!("Graph background"),
                 RailUIColorName::GraphTimeSlider => const_cstr!("Graph time slider"),
@@ -85,6 +87,12 @@ lazy_static! {
                 RailUIColorName::GraphBlockBorder => const_cstr!("Graph block border"),
                 RailUIColorName::GraphBlockReserved => const_cstr!("Graph block reserved"),
                 RailUIColorName::GraphBlockOccupied => const_cstr!("Graph block occupied"),
+                RailUIColorName::GraphBlockCompressed => const_cstr!("Graph compressed block"),
+                RailUIColorName::GraphBlockRouteSetting => const_cstr!("Graph blocking time route setting"),
+                RailUIColorName::GraphBlockApproach => const_cstr!("Graph blocking time approach"),
+                RailUIColorName::GraphBlockRunning => const_cstr!("Graph blocking time running"),
+                RailUIColorName::GraphBlockClearing => const_cstr!("Graph blocking time clearing"),
+                RailUIColorName::GraphBlockRelease => const_cstr!("Graph blocking time release"),
                 RailUIColorName::GraphTrainFront => const_cstr!("Graph train front"),
                 RailUIColorName::GraphTrainRear => const_cstr!("Graph train rear"),
                 RailUIColorName::GraphCommandRoute => const_cstr!("Graph command route"),
@@ -258,6 +266,8 @@ pub fn default_colors() -> EnumMap<RailUIColorName, Color> {
         RailUIColorName::CanvasTVDReserved => c(named::SLATEBLUE),
         RailUIColorName::CanvasRoutePath => c(named::DARKSLATEBLUE),
         RailUIColorName::CanvasRouteSection => c(named::SLATEBLUE),
+        RailUIColorName::CanvasRouteOverlap => c(named::ORANGE),
+        RailUIColorName::CanvasRouteFlank => c(named::DODGERBLUE),
         RailUIColorName::CanvasSelectionWindow => c(named::NAVY),
         RailUIColorName::GraphBackground => c(named::HONEYDEW),
         RailUIColorName::GraphTimeSlider => c(named::LIGHTSALMON),
@@ -265,6 +275,12 @@ pub fn default_colors() -> EnumMap<RailUIColorName, Color> {
         RailUIColorName::GraphBlockBorder => c(named::IVORY),
         RailUIColorName::GraphBlockReserved => c(named::LIGHTSKYBLUE),
         RailUIColorName::GraphBlockOccupied => c(named::LIGHTPINK),
+        RailUIColorName::GraphBlockCompressed => c(named::DARKORANGE),
+        RailUIColorName::GraphBlockRouteSetting => c(named::LIGHTGREY),
+        RailUIColorName::GraphBlockApproach => c(named::LIGHTSKYBLUE),
+        RailUIColorName::GraphBlockRunning => c(named::LIGHTPINK),
+        RailUIColorName::GraphBlockClearing => c(named::PLUM),
+        RailUIColorName::GraphBlockRelease => c(named::LIGHTSTEELBLUE),
         RailUIColorName::GraphTrainFront => c(named::TOMATO),
         RailUIColorName::GraphTrainRear => c(named::TOMATO),
         RailUIColorName::GraphCommandRoute => c(named::LIMEGREEN),
@@ -297,6 +313,8 @@ pub enum RailUIColorName {
     CanvasTVDReserved,
     CanvasRoutePath,
     CanvasRouteSection,
+    CanvasRouteOverlap,
+    CanvasRouteFlank,
     CanvasSelectionWindow,
     GraphBackground,
     GraphTimeSlider,
@@ -304,6 +322,12 @@ pub enum RailUIColorName {
     GraphBlockBorder,
     GraphBlockReserved,
     GraphBlockOccupied,
+    GraphBlockCompressed,
+    GraphBlockRouteSetting,
+    GraphBlockApproach,
+    GraphBlockRunning,
+    GraphBlockClearing,
+    GraphBlockRelease,
     GraphTrainFront,
     GraphTrainRear,
     GraphCommandRoute,
@@ -14,7 +14,10 @@ use crate::util;
 use crate::util::VecMap;
 use crate::document::dispatch;
 use crate::document::plan;
+use crate::document::dynamics;
+use crate::document::issues::Issue;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use nalgebra_glm as glm;
 
 pub type Generation = usize;
@@ -27,6 +30,11 @@ pub struct AnalysisOutput {
     pub dispatch :Vec<Option<(Generation, dispatch::DispatchOutput)>>,
     //pub plandispatches :HashMap<usize, Vec<Option<(Generation, dispatch::DispatchOutput)>>>,
     pub plandispatches :Vec<Option<(Generation, Vec<dispatch::DispatchOutput>)>>,
+    pub plandiagnostics :Vec<Option<(Generation, plan::PlanDiagnostics)>>,
+    /// Minimum cycle times of planned dispatches by plan and dispatch key
+    /// (see `Analysis::request_cycle_time`). `None` while it is computed.
+    pub cycle_times :HashMap<(usize,u64), Option<Result<Option<f64>,String>>>,
+    pub issues :Vec<Issue>,
 }
 
 pub struct Analysis {
@@ -34,6 +42,10 @@ pub struct Analysis {
     model_generation: Generation,
     output: AnalysisOutput,
     chan :Option<Receiver<SetData>>,
+    tx :Option<Sender<SetData>>,
+    /// Set when the background job is made obsolete by a newer model, 
+    /// so that the planner can stop searching.
+    cancel :Arc<AtomicBool>,
     bg :app::BackgroundJobs,
 }
 
@@ -43,6 +55,9 @@ pub enum SetData {
     Interlocking(Generation, Arc<interlocking::Interlocking>),
     Dispatch(Generation, usize,dispatch::DispatchOutput),
     PlanDispatch(Generation, usize,Vec<dispatch::DispatchOutput>),
+    PlanDiagnostics(Generation, usize, plan::PlanDiagnostics),
+    CycleTime(Generation, usize, u64, Result<Option<f64>,String>),
+    Issues(Generation, Vec<Issue>),
 }
 
 impl app::BackgroundUpdates for Analysis {
@@ -61,6 +76,13 @@ impl app::BackgroundUpdates for Analysis {
                         //.vecmap_insert(dispatch_idx, (g, h));
                     self.output.plandispatches.vecmap_insert(plan_idx, (g,hs));
                 },
+                SetData::PlanDiagnostics(g, plan_idx, d) => {
+                    self.output.plandiagnostics.vecmap_insert(plan_idx, (g,d));
+                },
+                SetData::CycleTime(_g, plan_idx, key, t) => {
+                    self.output.cycle_times.insert((plan_idx,key), Some(t));
+                },
+                SetData::Issues(_g, issues) => { self.output.issues.extend(issues); },
             }
         }
     }
@@ -77,6 +99,8 @@ impl Analysis {
             model_generation: 0,
             output: Default::default(),
             chan: None,
+            tx: None,
+            cancel: Arc::new(AtomicBool::new(false)),
             bg: bg,
         };
         a.update();
@@ -87,11 +111,24 @@ impl Analysis {
         let model = self.model.get().clone(); // persistent structs
         let gen = self.model_generation;
 
-        let topology = Arc::new(topology::convert(&model, 50.0).unwrap());
-        self.output.topology = Some((gen,topology.clone()));
-
         let (tx,rx) = channel();
         self.chan = Some(rx);
+        self.tx = Some(tx.clone());
+        self.output.cycle_times.clear();
+        self.cancel.store(true, Ordering::Relaxed);
+        self.cancel = Arc::new(AtomicBool::new(false));
+        let cancel = self.cancel.clone();
+
+        let topology = match topology::convert(&model, 50.0) {
+            Ok(t) => Arc::new(t),
+            Err(issue) => {
+                error!("Topology conversion failed: {}", issue.message());
+                self.output.issues = vec![issue];
+                return;
+            },
+        };
+        self.output.topology = Some((gen,topology.clone()));
+        self.output.issues = topology.issues.clone();
 
         self.bg.execute(move || {
             info!("Background thread starting");
@@ -99,8 +136,18 @@ impl Analysis {
             let tx = tx;        // move sender into thread
 
             //let dgraph = dgraph::calc(&model); // calc dgraph from model.
-            let dgraph = DGraphBuilder::convert(&topology).expect("dgraph conversion failed");
-            let dgraph = Arc::new(dgraph);
+            let dgraph = match DGraphBuilder::convert(&topology) {
+                Ok(d) => Arc::new(d),
+                Err(issue) => {
+                    error!("DGraph conversion failed: {}", issue.message());
+                    let _ = tx.send(SetData::Issues(gen, vec![issue]));
+                    return;
+                },
+            };
+            if dgraph.issues.len() > 0 {
+                let send_ok = tx.send(SetData::Issues(gen, dgraph.issues.clone()));
+                if !send_ok.is_ok() { println!("job canceled after dgraph issues"); return; }
+            }
 
             info!("Dgraph successful with {:?} nodes", dgraph.rolling_inf.nodes.len());
 
@@ -113,19 +160,36 @@ impl Analysis {
             // receiver end of the channel, so it will anyway not
             // be placed into the struct.
 
-            let interlocking = interlocking::calc(&dgraph); 
-            let interlocking = Arc::new(interlocking);
+            let interlocking = match interlocking::calc(&dgraph, &model.settings.interlocking) {
+                Ok(il) => Arc::new(il),
+                Err(issue) => {
+                    error!("Interlocking failed: {}", issue.message());
+                    let _ = tx.send(SetData::Issues(gen, vec![issue]));
+                    return;
+                },
+            };
                 // calc interlocking from dgraph
             let send_ok = tx.send(SetData::Interlocking(gen, interlocking.clone()));
             if !send_ok.is_ok() { println!("job canceled after interlocking"); return; }
             info!("Interlocking successful with {:?} routes", interlocking.routes.len());
 
+            let vehicles = dynamics::vehicles(&model);
             for (i,dispatch) in model.dispatches.iter() {
                 //let history = dispatch::run(&dgraph, &interlocking, &dispatch);
-                let (history,route_refs) = history::get_history(model.vehicles.data(),
-                                                   &dgraph.rolling_inf,
+                let (history,route_refs) = match history::get_history(&vehicles,
+                                                   &dgraph,
                                                    &interlocking,
-                                                   &(dispatch.commands)).unwrap();
+                                                   &(dispatch.commands),
+                                                   &(dispatch.trains)) {
+                    Ok(h) => h,
+                    Err(e) => {
+                        error!("Simulation failed for {:?}: {}", dispatch.name, e);
+                        let issue = Issue::Simulation(dispatch.name.clone(), e);
+                        let send_ok = tx.send(SetData::Issues(gen, vec![issue]));
+                        if !send_ok.is_ok() { println!("job canceled after dispatch issue"); return; }
+                        continue;
+                    },
+                };
                 info!("Simulation successful {:?}", &dispatch.commands);
                 let view = dispatch::DispatchOutput::from_history(dispatch.clone(), &dgraph, history);
                 let send_ok = tx.send(SetData::Dispatch(gen, *i, view));
@@ -133,9 +197,21 @@ impl Analysis {
             }
 
             for (plan_idx,plan) in model.plans.iter() {
-                let planresults = plan::get_dispatches(&dgraph, &interlocking,
-                                             model.vehicles.data(),
-                                             plan).unwrap();
+                let planresults = match plan::get_dispatches(&dgraph, &interlocking,
+                                             &vehicles, plan, &cancel) {
+                    Ok((r,diagnostics)) => {
+                        let send_ok = tx.send(SetData::PlanDiagnostics(gen, *plan_idx, diagnostics));
+                        if !send_ok.is_ok() { println!("job cancelled after plan diagnostics {}", plan_idx); }
+                        r
+                    },
+                    Err(e) => {
+                        error!("Planning failed for {:?}: {}", plan.name, e);
+                        let issue = Issue::Planning(plan.name.clone(), e);
+                        let send_ok = tx.send(SetData::Issues(gen, vec![issue]));
+                        if !send_ok.is_ok() { println!("job cancelled after plan issue {}", plan_idx); return; }
+                        Vec::new()
+                    },
+                };
 
                 info!("Planning successful. {:?}", planresults);
 
@@ -150,6 +226,37 @@ impl Analysis {
         });
     }
 
+    /// Find the minimum cycle time of a planned dispatch in a background
+    /// job, unless it is already known or being computed. This takes many
+    /// simulations, so it is only done for the dispatches the user looks at.
+    /// The result is put in `AnalysisOutput::cycle_times`.
+    pub fn request_cycle_time(&mut self, plan_idx :usize, dispatch_idx :usize) {
+        let gen = self.model_generation;
+        let (dgraph, il) = match (&self.output.dgraph, &self.output.interlocking) {
+            (Some((g1,dgraph)), Some((g2,il))) if *g1 == gen && *g2 == gen => (dgraph.clone(), il.clone()),
+            _ => return,
+        };
+        let dispatch = match self.output.plandispatches.get(plan_idx) {
+            Some(Some((g,dispatches))) if *g == gen => match dispatches.get(dispatch_idx) {
+                Some(d) => d.dispatch.clone(),
+                None => return,
+            },
+            _ => return,
+        };
+        let tx = match &self.tx { Some(tx) => tx.clone(), None => return };
+        let key = plan::dispatch_key(&dispatch.commands);
+        if self.output.cycle_times.contains_key(&(plan_idx,key)) { return; }
+        self.output.cycle_times.insert((plan_idx,key), None);
+
+        let vehicles = dynamics::vehicles(self.model());
+        self.bg.execute(move || {
+            let result = history::get_history(&vehicles, &dgraph, &il, &dispatch.commands, &dispatch.trains)
+                .and_then(|(history,_)| plan::min_cycle_time(&dgraph, &il, &vehicles, 
+                                                             &dispatch.commands, &history));
+            let _ = tx.send(SetData::CycleTime(gen, plan_idx, key, result));
+        });
+    }
+
     pub fn edit_model(&mut self, mut f :impl FnOnce(&mut Model) -> Option<EditClass>) {
         let mut new_model = self.model.get().clone();
         let cl = f(&mut new_model);
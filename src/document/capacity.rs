@@ -0,0 +1,220 @@
+// Capacity consumption in the style of UIC leaflet 406.
+//
+// Capacity consumption is calculated for a line section, given as a
+// mileage interval of the time-distance diagram. The blocking times of
+// each train inside the line section are pushed as close as possible to
+// the previous train's blocking times, keeping the order of the trains,
+// which gives the compressed timetable. The time the compressed timetable
+// occupies the line section, relative to the time period of the original
+// timetable, is the line section's capacity consumption.
+
+use std::cell::{RefCell, Ref};
+use std::collections::HashMap;
+use rolling::input::staticinfrastructure as rolling_inf;
+
+use crate::document::dgraph::DGraph;
+use crate::document::dispatch::{Diagram, BlockingTime, blocking_times};
+use crate::document::model::TimingSettings;
+use crate::document::routetable::{section_name, csv_field};
+
+#[derive(Debug, Clone)]
+pub struct CompressedBlock {
+    pub pos :(f64,f64),
+    /// Full blocking time, from the start of route setting to release.
+    pub blocking :(f64,f64),
+    pub train :usize,
+    pub section :rolling_inf::ObjectId,
+}
+
+#[derive(Debug, Clone)]
+pub struct SectionCapacity {
+    pub section :rolling_inf::ObjectId,
+    pub pos :(f64,f64),
+    /// Time from the first to the last blocking time in the compressed timetable.
+    pub occupation :f64,
+    pub percent :f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Capacity {
+    /// Mileage interval of the line section.
+    pub line_section :(f64,f64),
+    /// Time period of the original timetable.
+    pub period :f64,
+    /// Time from the first to the last blocking time in the compressed timetable.
+    pub occupation :f64,
+    pub percent :f64,
+    pub blocks :Vec<CompressedBlock>,
+    pub sections :Vec<SectionCapacity>,
+}
+
+/// Capacity consumption in percent of the given time period.
+pub fn consumption(occupation :f64, period :f64) -> f64 {
+    if period > 0.0 { 100.0 * occupation / period } else { 0.0 }
+}
+
+fn blocking_interval(b :&BlockingTime) -> (f64,f64) {
+    let t0 = b.bands.iter().map(|(_,(t,_))| *t).fold(std::f64::INFINITY, f64::min);
+    let t1 = b.bands.iter().map(|(_,(_,t))| *t).fold(-std::f64::INFINITY, f64::max);
+    (t0,t1)
+}
+
+pub fn compress(diagram :&Diagram, period :f64, timing :&TimingSettings, line_section :(f64,f64)) -> Capacity {
+    let (l0,l1) = line_section;
+    let blocks = blocking_times(diagram, timing).into_iter()
+        .filter(|b| b.pos.0.min(b.pos.1) < l1 && b.pos.0.max(b.pos.1) > l0)
+        .map(|b| CompressedBlock {
+            pos: (b.pos.0.max(l0).min(l1), b.pos.1.max(l0).min(l1)),
+            blocking: blocking_interval(&b),
+            train: b.train,
+            section: b.section,
+        }).collect::<Vec<_>>();
+
+    // Trains in the order of their first blocking time.
+    let mut trains :HashMap<usize, Vec<&CompressedBlock>> = HashMap::new();
+    for b in blocks.iter() { trains.entry(b.train).or_insert(Vec::new()).push(b); }
+    let mut trains = trains.into_iter().collect::<Vec<_>>();
+    let start = |bs :&[&CompressedBlock]| bs.iter().map(|b| b.blocking.0).fold(std::f64::INFINITY, f64::min);
+    trains.sort_by(|(_,a),(_,b)| start(a.as_slice()).partial_cmp(&start(b.as_slice())).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut compressed :Vec<CompressedBlock> = Vec::new();
+    let mut prev_start = None;
+    for (train, train_blocks) in trains.iter() {
+        let train_start = start(train_blocks.as_slice());
+
+        // Move the train back in time until one of its blocking times
+        // touches a blocking time of an earlier train in the same section,
+        // but not to before the start of the previous train.
+        let mut shift = match prev_start { Some(t) => train_start - t, None => 0.0 };
+        for b in train_blocks.iter() {
+            for c in compressed.iter().filter(|c| c.section == b.section) {
+                shift = shift.min(b.blocking.0 - c.blocking.1);
+            }
+        }
+        let shift = shift.max(0.0);
+
+        prev_start = Some(train_start - shift);
+        compressed.extend(train_blocks.iter().map(|b| CompressedBlock {
+            pos: b.pos,
+            blocking: (b.blocking.0 - shift, b.blocking.1 - shift),
+            train: *train,
+            section: b.section,
+        }));
+    }
+
+    // Section -> (position interval, time interval)
+    let mut intervals :HashMap<rolling_inf::ObjectId, ((f64,f64),(f64,f64))> = HashMap::new();
+    for b in compressed.iter() {
+        let (pos,time) = intervals.entry(b.section).or_insert((b.pos, b.blocking));
+        *pos = (pos.0.min(b.pos.0), pos.1.max(b.pos.1));
+        *time = (time.0.min(b.blocking.0), time.1.max(b.blocking.1));
+    }
+    let mut sections = intervals.into_iter().map(|(section,(pos,time))| SectionCapacity {
+        section, pos, occupation: time.1 - time.0, percent: consumption(time.1 - time.0, period),
+    }).collect::<Vec<_>>();
+    sections.sort_by(|a,b| a.pos.0.partial_cmp(&b.pos.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let t0 = compressed.iter().map(|b| b.blocking.0).fold(std::f64::INFINITY, f64::min);
+    let t1 = compressed.iter().map(|b| b.blocking.1).fold(-std::f64::INFINITY, f64::max);
+    let occupation = if compressed.is_empty() { 0.0 } else { t1 - t0 };
+
+    Capacity { line_section, period, occupation, percent: consumption(occupation, period),
+               blocks: compressed, sections }
+}
+
+/// The compressed timetable of a dispatch, kept for the line section and
+/// timing settings it was last calculated for.
+#[derive(Debug, Default)]
+pub struct CapacityCache {
+    cached :RefCell<Option<(TimingSettings, Capacity)>>,
+}
+
+impl CapacityCache {
+    pub fn get(&self, diagram :&Diagram, period :f64, timing :&TimingSettings, 
+               line_section :(f64,f64)) -> Ref<Capacity> {
+        let valid = match &*self.cached.borrow() {
+            Some((t,c)) => t == timing && c.line_section == line_section,
+            None => false,
+        };
+        if !valid {
+            let capacity = compress(diagram, period, timing, line_section);
+            *self.cached.borrow_mut() = Some((timing.clone(), capacity));
+        }
+        Ref::map(self.cached.borrow(), |c| &c.as_ref().unwrap().1)
+    }
+}
+
+pub fn to_csv(dgraph :&DGraph, capacity :&Capacity) -> String {
+    let mut out = format!("section,from_km,to_km,occupation_s,period_s,consumption_pct\n");
+    for s in capacity.sections.iter() {
+        out.push_str(&format!("{},{:.3},{:.3},{:.1},{:.1},{:.1}\n", csv_field(&section_name(dgraph, s.section)),
+                              s.pos.0, s.pos.1, s.occupation, capacity.period, s.percent));
+    }
+    out.push_str(&format!("total,{:.3},{:.3},{:.1},{:.1},{:.1}\n",
+                          capacity.line_section.0, capacity.line_section.1,
+                          capacity.occupation, capacity.period, capacity.percent));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::dispatch::BlockGraph;
+
+    fn block(train :usize, section :usize, pos :(f64,f64), occupied :(f64,f64)) -> BlockGraph {
+        BlockGraph { pos, reserved: occupied, occupied, train, section, info: String::new() }
+    }
+
+    fn no_margins() -> TimingSettings {
+        TimingSettings { route_setting: 0.0, sight: 0.0, release: 0.0 }
+    }
+
+    #[test]
+    pub fn percentage() {
+        assert_eq!(consumption(30.0, 120.0), 25.0);
+        assert_eq!(consumption(30.0, 0.0), 0.0);
+    }
+
+    #[test]
+    pub fn compress_following_trains() {
+        // Two trains running through sections 1 and 2, 100 s apart.
+        let diagram = Diagram { trains: vec![], blocks: vec![
+            block(0, 1, (0.0, 1000.0), (0.0, 60.0)),
+            block(0, 2, (1000.0, 2000.0), (50.0, 110.0)),
+            block(1, 1, (0.0, 1000.0), (100.0, 160.0)),
+            block(1, 2, (1000.0, 2000.0), (150.0, 210.0)),
+        ]};
+        let capacity = compress(&diagram, 400.0, &no_margins(), (0.0, 2000.0));
+        // The second train is moved back until its blocking times touch the first train's.
+        assert_eq!(capacity.occupation, 170.0);
+        assert_eq!(capacity.percent, 42.5);
+        assert_eq!(capacity.sections.len(), 2);
+        assert_eq!(capacity.sections[0].occupation, 120.0);
+        assert_eq!(capacity.sections[0].percent, 30.0);
+
+        // With margins, the full blocking times are compressed.
+        let timing = TimingSettings { route_setting: 5.0, sight: 10.0, release: 5.0 };
+        let capacity = compress(&diagram, 400.0, &timing, (0.0, 2000.0));
+        assert_eq!(capacity.occupation, 210.0);
+        assert_eq!(capacity.blocks.iter().find(|b| b.train == 1 && b.section == 1).unwrap().blocking,
+                   (65.0, 145.0));
+    }
+
+    #[test]
+    pub fn compress_line_section() {
+        // Only the second section is in the line section, which the 
+        // trains use for a shorter time than the first section.
+        let diagram = Diagram { trains: vec![], blocks: vec![
+            block(0, 1, (0.0, 1000.0), (0.0, 100.0)),
+            block(0, 2, (1000.0, 2000.0), (90.0, 120.0)),
+            block(1, 1, (0.0, 1000.0), (200.0, 300.0)),
+            block(1, 2, (1000.0, 2000.0), (290.0, 320.0)),
+        ]};
+        let whole = compress(&diagram, 400.0, &no_margins(), (0.0, 2000.0));
+        let part = compress(&diagram, 400.0, &no_margins(), (1000.0, 2000.0));
+        assert_eq!(whole.occupation, 220.0);
+        assert_eq!(part.occupation, 60.0);
+        assert_eq!(part.percent, 15.0);
+        assert!(part.blocks.iter().all(|b| b.section == 2));
+    }
+}
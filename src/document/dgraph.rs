@@ -6,12 +6,19 @@ use crate::document::model::*;
 use crate::document::objects::*;
 use crate::document::topology::*;
 use crate::document::mileage;
+use crate::document::issues::Issue;
 use matches::matches;
 use nalgebra_glm as glm;
 
 pub type ModelNodeId = Pt;
 pub type ModelObjectId = PtA;
 
+/// Difference (m) between a pinned and computed mileage that is reported as a conflict.
+const MILEAGE_TOLERANCE :f64 = 1.0;
+/// Distance (m) kept between gradient sections and the ends of their track,
+/// so that the sections do not start or end on a switch.
+const GRADIENT_MARGIN :f64 = 1.0;
+
 pub mod allpaths;
 
 #[derive(Debug)]
@@ -24,12 +31,28 @@ pub struct DGraph {
     pub tvd_edges :HashMap<rolling_inf::ObjectId, Vec<(rolling_inf::NodeId, rolling_inf::NodeId)>>,
     pub tvd_entry_nodes :HashMap<rolling_inf::ObjectId, Vec<rolling_inf::NodeId>>,
     pub edge_lines :HashMap<(rolling_inf::NodeId, rolling_inf::NodeId), Vec<PtC>>,
+    /// Sections of constant gradient, once for each direction of travel.
+    pub gradient_sections :Vec<GradientSection>,
     pub mileage :HashMap<rolling_inf::NodeId, f64>,
     pub all_paths :(f64, Vec<allpaths::Path>),
+    pub issues :Vec<Issue>,
 }
 
 
 
+/// A track section with constant gradient, for one direction of travel.
+#[derive(Debug, Clone)]
+pub struct GradientSection {
+    /// Node where trains enter the section, facing into it.
+    pub start :rolling_inf::NodeId,
+    /// Node where trains leave the section, facing out of it.
+    pub end :rolling_inf::NodeId,
+    /// Gradient (per mille), positive uphill in the direction of travel.
+    pub gradient :f64,
+    /// Speed limit (m/s) in effect when entering the section, if known.
+    pub line_speed :Option<f64>,
+}
+
 impl DGraph {
     pub fn mileage_at(&self, a :rolling_inf::NodeId, b :rolling_inf::NodeId, param :f64) -> Option<f64> {
         let km_a = *self.mileage.get(&a)?;
@@ -65,7 +88,7 @@ pub struct Interval {
 }
 
 impl DGraphBuilder {
-    pub fn convert(topology :&Topology) -> Result<DGraph, ()> {
+    pub fn convert(topology :&Topology) -> Result<DGraph, Issue> {
         let mut m = DGraphBuilder::new();
 
         let tracks = &topology.tracks;
@@ -79,19 +102,43 @@ impl DGraphBuilder {
         let mut detector_nodes : HashSet<(rolling_inf::NodeId, rolling_inf::NodeId)> = HashSet::new();
         let mut object_ids = BiMap::new();
         let mut detector_ids = BiMap::new();
-        let (node_ids, switch_ids, crossing_edges) = m.create_network(
-            tracks, &locs, 
+        let mut issues = Vec::new();
+        let mut gradient_sections = Vec::new();
+        let (node_ids, node_ports, switch_ids, crossing_edges) = m.create_network(
+            tracks, &locs, &topology.node_speeds,
             |track_idx,mut cursor,dg| {
                 let mut last_pos = 0.0;
-                let mut objs :Vec<(f64,PtA,Function,Option<AB>)> = trackobjects[track_idx].clone();
-                objs.sort_by_key(|(pos,_,_,_)| OrderedFloat(*pos));
-                for (pos, id, func, dir) in objs {
+                let mut objs :Vec<(f64,Option<(PtA,Function,Option<AB>)>)> = trackobjects[track_idx].iter()
+                    .map(|(pos,id,func,dir)| (*pos, Some((*id,*func,*dir)))).collect();
+
+                // Nodes at the ends of gradient sections, where the simulation puts speed restrictions.
+                let gradients = gradient_intervals(&topology.gradients[track_idx], tracks[track_idx].0);
+                objs.extend(gradients.iter().flat_map(|(g0,g1,_)| vec![(*g0, None), (*g1, None)]));
+                let mut gradient_nodes = HashMap::new();
+
+                objs.sort_by_key(|(pos,_)| OrderedFloat(*pos));
+                for (pos, obj) in objs {
 
                     // TODO stack overflow here
-                    cursor = cursor.advance_single(&dg.dgraph, pos - last_pos).unwrap();
+                    cursor = match cursor.advance_single(&dg.dgraph, pos - last_pos) {
+                        Some(c) => c,
+                        None => { 
+                            if let Some((id,_,_)) = obj { issues.push(Issue::ObjectPlacement(id)); }
+                            continue; 
+                        },
+                    };
 
                     cursor = dg.insert_node_pair(cursor);
 
+                    let (id, func, dir) = match obj {
+                        Some(obj) => obj,
+                        None => {
+                            gradient_nodes.insert(OrderedFloat(pos), cursor.nodes(&dg.dgraph).0);
+                            last_pos = pos;
+                            continue;
+                        },
+                    };
+
                     match func {
                         Function::Detector => { 
                             let (a,b) = cursor.nodes(&dg.dgraph);
@@ -108,9 +155,24 @@ impl DGraphBuilder {
                             static_signals.insert(id, obj);
                             object_ids.insert(obj, id);
                         },
+                        Function::SpeedLimit { speed } => {
+                            let c = if matches!(dir,Some(AB::B)) { cursor.reverse(&dg.dgraph) } else { cursor };
+                            dg.insert_object(c, speed_restriction(speed));
+                        },
                     }
                     last_pos = pos;
                 }
+
+                for (g0,g1,gradient) in gradients {
+                    if let (Some(a), Some(b)) = (gradient_nodes.get(&OrderedFloat(g0)), 
+                                                 gradient_nodes.get(&OrderedFloat(g1))) {
+                        gradient_sections.push(GradientSection { start: *a, end: *b, 
+                            gradient, line_speed: None });
+                        gradient_sections.push(GradientSection { 
+                            start: dg.dgraph.nodes[*b].other_node, end: dg.dgraph.nodes[*a].other_node,
+                            gradient: -gradient, line_speed: None });
+                    }
+                }
             } );
 
         // Sight to signals
@@ -134,7 +196,11 @@ impl DGraphBuilder {
         let (tvd_edges,tvd_entry_nodes) = route_finder::detectors_to_sections(&mut m.dgraph, 
                                                                               &detector_nodes,
                                                                               &crossing_edges)
-            .expect("could not calc tvd sections.");
+            .map_err(Issue::DetectionSections)?;
+
+        for section in gradient_sections.iter_mut() {
+            section.line_speed = speed_limit_at(&m.dgraph, section.start);
+        }
 
         let mut edge_lines :HashMap<(rolling_inf::NodeId, rolling_inf::NodeId), Vec<PtC>>
             = m.edge_tracks.into_iter()
@@ -144,7 +210,32 @@ impl DGraphBuilder {
         let rev_edge_lines = edge_lines.iter().map(|((a,b),v)| ((*b,*a),{ let mut v= v.clone(); v.reverse(); v })).collect::<Vec<_>>();
         edge_lines.extend(rev_edge_lines.into_iter());
 
-        let mileage = mileage::auto(&node_ids, &m.dgraph);
+        // Pinned mileages on nodes and objects
+        let mut pins = HashMap::new();
+        let mut pin_refs = Vec::new();
+        for (r,km) in topology.mileages.iter() {
+            let node = match r {
+                Ref::Node(pt) => node_ports.get(pt).cloned(),
+                Ref::Object(pta) => detector_ids.get_by_right(pta).cloned().or_else(|| {
+                    let obj = object_ids.get_by_right(pta)?;
+                    m.dgraph.nodes.iter().position(|n| n.objects.contains(obj))
+                }),
+                Ref::LineSeg(_,_) => None,
+            };
+            match node {
+                Some(node) => { pins.insert(node, *km); pin_refs.push((*r, node, *km)); },
+                None => { issues.push(Issue::MileagePlacement(*r)); },
+            }
+        }
+
+        let mileage = mileage::auto(&node_ids, &m.dgraph, &pins);
+        for (r,node,km) in pin_refs {
+            if let Some(computed) = mileage.get(&node) {
+                if (computed - km).abs() > MILEAGE_TOLERANCE {
+                    issues.push(Issue::ConflictingMileage(r, km, *computed));
+                }
+            }
+        }
         //println!("MILEAGES {:?}", mileage);
         //mileage::test_lsq();
         //let mileage = std::iter::empty().collect();
@@ -160,8 +251,10 @@ impl DGraphBuilder {
             tvd_edges: tvd_edges,
             tvd_entry_nodes: tvd_entry_nodes,
             edge_lines: edge_lines,
+            gradient_sections: gradient_sections,
             mileage: mileage,
             all_paths: all_paths,
+            issues: issues,
         })
 
     }
@@ -260,8 +353,10 @@ impl DGraphBuilder {
     pub fn create_network(&mut self,
         tracks: &[(f64, (Pt, Port), (Pt, Port))], // track length and line pieces
         nodes: &HashMap<Pt,(NDType, Vc)>,
+        node_speeds: &HashMap<Pt,u32>,
         mut each_track: impl FnMut(usize,Cursor,&mut Self)) -> 
         (BiMap<rolling_inf::NodeId, Pt>,
+         HashMap<Pt, rolling_inf::NodeId>,
          BiMap<rolling_inf::ObjectId, Pt>,
          HashSet<(rolling_inf::NodeId, rolling_inf::NodeId)>) {
 
@@ -307,6 +402,15 @@ impl DGraphBuilder {
                         rolling_inf::Edges::Single(ports[&(*pt,Port::Trunk)], 0.0);
                     self.dgraph.nodes[ports[&(*pt, Port::Trunk)]].edges =
                         rolling_inf::Edges::Switchable(sw_obj);
+
+                    // The restriction is passed when leaving the switch onto the diverging 
+                    // branch, and when entering the switch from the diverging branch.
+                    if let Some(speed) = node_speeds.get(pt) {
+                        let branch = ports[&(*pt, if *side == Side::Left { Port::Left } else { Port::Right })];
+                        let other = self.dgraph.nodes[branch].other_node;
+                        self.new_object_at(speed_restriction(*speed), branch);
+                        self.new_object_at(speed_restriction(*speed), other);
+                    }
                 },
                 NDType::Crossing(type_) => {
                     let left_drivable  = matches!(type_, CrossingType::DoubleSlip | CrossingType::SingleSlip(Side::Left));
@@ -339,12 +443,63 @@ impl DGraphBuilder {
                         crossing_edges.insert((ports[&(*pt, Port::Cross(AB::A, 0))], ports[&(*pt, Port::Cross(AB::A, 1))]));
                     }
 
+                    // Restrict any train entering the crossing.
+                    if let Some(speed) = node_speeds.get(pt) {
+                        for ab in &[AB::A, AB::B] { for i in 0..2 {
+                            let entry = self.dgraph.nodes[ports[&(*pt, Port::Cross(*ab, i))]].other_node;
+                            self.new_object_at(speed_restriction(*speed), entry);
+                        } }
+                    }
+
                 },
                 NDType::Err => {},
             }
         }
-        (node_ids, switch_ids, crossing_edges)
+        // Any node at each model node, for locating pinned mileages.
+        let node_ports = ports.iter().map(|((pt,_),node)| (*pt,*node)).collect();
+        (node_ids, node_ports, switch_ids, crossing_edges)
+    }
+}
+
+/// Intervals of constant, non-zero gradient on a track of the given length. 
+/// Adjacent intervals with the same gradient are merged, and the intervals
+/// are kept `GRADIENT_MARGIN` inside the track ends.
+fn gradient_intervals(gradients :&[(f64,f64,f64)], length :f64) -> Vec<(f64,f64,f64)> {
+    let mut intervals :Vec<(f64,f64,f64)> = Vec::new();
+    for (g0,g1,g) in gradients.iter() {
+        if *g == 0.0 { continue; }
+        match intervals.last_mut() {
+            Some((_,end,prev)) if (*end - *g0).abs() < 1e-6 && *prev == *g => { *end = *g1; },
+            _ => { intervals.push((*g0,*g1,*g)); },
+        }
     }
+    intervals.into_iter()
+        .map(|(g0,g1,g)| (g0.max(GRADIENT_MARGIN), g1.min(length - GRADIENT_MARGIN), g))
+        .filter(|(g0,g1,_)| g1 > g0).collect()
+}
+
+/// The speed limit (m/s) for trains leaving `node`, found by following the
+/// track backwards to the nearest speed restriction. `None` if there is no
+/// speed restriction before the track branches or ends.
+fn speed_limit_at(inf :&rolling_inf::StaticInfrastructure, mut node :rolling_inf::NodeId) -> Option<f64> {
+    let mut visited = HashSet::new();
+    while visited.insert(node) {
+        let limit = inf.nodes[node].objects.iter().filter_map(|obj| match inf.objects[*obj] {
+            rolling_inf::StaticObject::SpeedRestriction(v) => Some(v),
+            _ => None,
+        }).next();
+        if limit.is_some() { return limit; }
+        match inf.nodes[inf.nodes[node].other_node].edges {
+            rolling_inf::Edges::Single(prev,_) => { node = prev; },
+            _ => { return None; },
+        }
+    }
+    None
+}
+
+/// Speed limit object from a speed in km/h.
+fn speed_restriction(speed :u32) -> rolling_inf::StaticObject {
+    rolling_inf::StaticObject::SpeedRestriction(speed as f64 / 3.6)
 }
 
 #[derive(Copy,Clone, Debug)]
@@ -6,6 +6,8 @@ use crate::document::objects::*;
 use crate::document::dgraph::*;
 use crate::document::history::*;
 use crate::document::analysis::*;
+use crate::document::interlocking::Interlocking;
+use crate::document::capacity::{Capacity, CapacityCache};
 
 use crate::util::VecMap;
 
@@ -22,6 +24,7 @@ pub struct DispatchOutput {
     pub pos_interval :(f32,f32),
     pub instant :Instant,
     pub diagram :Diagram, 
+    pub capacity :CapacityCache,
 }
 
 fn pos_range(diagram :&Diagram) -> (f32,f32) {
@@ -55,10 +58,90 @@ impl DispatchOutput {
             pos_interval: (pos1, pos2),
             instant: instant,
             diagram: diagram,
+            capacity: CapacityCache::default(),
         }
     }
+
+    /// The whole mileage interval of the diagram.
+    pub fn line(&self) -> (f64,f64) {
+        (self.pos_interval.0 as f64, self.pos_interval.1 as f64)
+    }
+
+    /// Capacity consumption of a line section, calculated when the line
+    /// section or timing settings have changed.
+    pub fn capacity(&self, timing :&TimingSettings, line_section :(f64,f64)) -> std::cell::Ref<Capacity> {
+        self.capacity.get(&self.diagram, self.max_t as f64, timing, line_section)
+    }
+
+    /// Copy the dispatch for manual editing. Commands are given the time
+    /// when their route was reserved in the simulation, so that route
+    /// requests waiting in the queue are issued when they actually happened.
+    /// A route is reserved when all its sections are reserved at the same 
+    /// time, and for a train command, by the train that it creates.
+    pub fn to_manual(&self, il :&Interlocking, name :String) -> Dispatch {
+        let reservations = reservations(&self.history);
+        let mut t_prev = 0.0;
+        let mut train_no = 0;
+        let commands = self.dispatch.commands.iter().enumerate().map(|(id,(_,(t_cmd,cmd)))| {
+            let (spec, train) = match cmd { 
+                Command::Route(spec) => (spec, None),
+                Command::Train(_,spec) => (spec, Some(train_no)),
+            };
+            let t_min = t_cmd.max(t_prev);
+            let route = il.find_route(spec).and_then(|r| il.routes.get(*r));
+            if route.is_some() && train.is_some() { train_no += 1; }
+            let issued = route.and_then(|r| route_reserved(&reservations, &r.route.resources.sections, t_min, train))
+                .unwrap_or(t_min);
+            t_prev = issued;
+            (id,(issued,*cmd))
+        }).collect();
+        Dispatch::from_vec(name, commands)
+    }
 }
 
+/// Section reservations in a history: the time, the section, and the first 
+/// train occupying the section while it is reserved.
+fn reservations(history :&History) -> Vec<(f64, ObjectId, Option<usize>)> {
+    use rolling::output::history::InfrastructureLogEvent;
+    let mut reservations = Vec::new();
+    let mut open :HashMap<ObjectId,usize> = HashMap::new();
+    let mut t = 0.0;
+    for infevent in &history.inf {
+        match infevent {
+            InfrastructureLogEvent::Wait(dt) => { t += dt; },
+            InfrastructureLogEvent::Reserved(tvd,on) if *on => { 
+                open.insert(*tvd, reservations.len());
+                reservations.push((t,*tvd,None)); 
+            },
+            InfrastructureLogEvent::Reserved(tvd,on) if !*on => { open.remove(tvd); },
+            InfrastructureLogEvent::Occupied(tvd,on,_,train) if *on => {
+                if let Some(idx) = open.get(tvd) {
+                    let (_,_,occupied_by) = &mut reservations[*idx];
+                    if occupied_by.is_none() { *occupied_by = Some(*train); }
+                }
+            },
+            _ => {},
+        }
+    }
+    reservations
+}
+
+/// The first time at or after `t_min` when all the sections were reserved 
+/// together, and, if `train` is given, later occupied by that train.
+fn route_reserved(reservations :&[(f64, ObjectId, Option<usize>)], sections :&[ObjectId], 
+                  t_min :f64, train :Option<usize>) -> Option<f64> {
+    let reserved_at = |tvd :&ObjectId, t :f64| reservations.iter()
+        .any(|(t_res,x,occupied_by)| x == tvd && (t_res - t).abs() < RESERVATION_TOLERANCE &&
+             (train.is_none() || occupied_by.is_none() || *occupied_by == train));
+    let first = sections.first()?;
+    reservations.iter()
+        .filter(|(t,tvd,_)| *t >= t_min && tvd == first)
+        .map(|(t,_,_)| *t)
+        .find(|t| sections.iter().all(|tvd| reserved_at(tvd, *t)))
+}
+
+const RESERVATION_TOLERANCE :f64 = 1e-3;
+
 pub type DispatchRef = (Result<usize, (usize,usize)>, f32);
 #[derive(Debug)]
 pub struct InstantCache {
@@ -164,6 +247,7 @@ pub struct BlockGraph {
     pub reserved :(f64,f64),
     pub occupied :(f64,f64),
     pub train :usize,
+    pub section :ObjectId,
     pub info: String,
 }
 
@@ -182,6 +266,47 @@ pub struct TrainGraphSegment {
     pub acc :f64,
 }
 
+/// Parts of a section's blocking time, in the order they happen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockingBand { RouteSetting, Approach, Running, Clearing, Release }
+
+/// Blocking time of a train in a section, split into bands.
+#[derive(Debug)]
+pub struct BlockingTime {
+    pub pos :(f64,f64),
+    pub train :usize,
+    pub section :ObjectId,
+    pub bands :Vec<(BlockingBand,(f64,f64))>,
+}
+
+/// Blocking time stairways from the simulated section occupations.
+/// The route setting, sight and release times are added around the
+/// reserved interval. The occupied interval is split into running time
+/// (until the front of the train enters the train's next section) and
+/// clearing time (until the rear of the train has left the section).
+pub fn blocking_times(diagram :&Diagram, timing :&TimingSettings) -> Vec<BlockingTime> {
+    diagram.blocks.iter().map(|b| {
+        let front_exit = diagram.blocks.iter()
+            .filter(|o| o.train == b.train && o.occupied.0 > b.occupied.0 && o.occupied.0 < b.occupied.1)
+            .map(|o| o.occupied.0)
+            .fold(b.occupied.1, f64::min);
+        let approach = b.reserved.0 - timing.sight;
+        let release = b.reserved.1.max(b.occupied.1);
+        BlockingTime {
+            pos: b.pos,
+            train: b.train,
+            section: b.section,
+            bands: vec![
+                (BlockingBand::RouteSetting, (approach - timing.route_setting, approach)),
+                (BlockingBand::Approach, (approach, b.occupied.0)),
+                (BlockingBand::Running, (b.occupied.0, front_exit)),
+                (BlockingBand::Clearing, (front_exit, b.occupied.1)),
+                (BlockingBand::Release, (b.occupied.1, release + timing.release)),
+            ],
+        }
+    }).collect()
+}
+
 pub fn get_km(dgraph :&DGraph, a :rolling_inf::NodeId, b :rolling_inf::NodeId, offset :f64) -> Option<f64> {
     let edge_length = edge_length(&dgraph.rolling_inf, a, b)?;
     let km1 = dgraph.mileage.get(&a)?;
@@ -202,8 +327,8 @@ fn plot_blocks(history :&History, dgraph :&DGraph) -> Vec<BlockGraph> {
     use rolling::output::history::*;
     let mut t = 0.0;
     let mut reserved : HashMap<ObjectId,f64> = HashMap::new(); // Reserved at time
-    let mut occupied : HashMap<ObjectId,(f64,(f64,NodeId))> = HashMap::new(); // Became occupied at time
-    let mut vacant : HashMap<ObjectId,(f64,(f64,NodeId),(f64,NodeId))> = HashMap::new(); // Became occupied at time
+    let mut occupied : HashMap<ObjectId,(f64,(f64,NodeId),usize)> = HashMap::new(); // Became occupied at time, by train
+    let mut vacant : HashMap<ObjectId,(f64,(f64,NodeId),(f64,NodeId),usize)> = HashMap::new(); // Became vacant at time
     for infevent in &history.inf {
         //println!("infevent {:?}", infevent);
         //println!("rserved {:?}", reserved);
@@ -214,23 +339,24 @@ fn plot_blocks(history :&History, dgraph :&DGraph) -> Vec<BlockGraph> {
             InfrastructureLogEvent::Reserved(tvd,on) if *on => { reserved.insert(*tvd, t); }
             InfrastructureLogEvent::Occupied(tvd,on,node,train) if *on => {
                 if let Some(reserved_t) = reserved.remove(tvd) {
-                    occupied.insert(*tvd, (reserved_t, (t, *node)));
+                    occupied.insert(*tvd, (reserved_t, (t, *node), *train));
                 }
             },
             InfrastructureLogEvent::Occupied(tvd,on,node,train) if !*on => {
-                if let Some((reserved_t,(occupied_t,occ_node))) = occupied.remove(tvd) {
-                    vacant.insert(*tvd, (reserved_t, (occupied_t,occ_node),(t, *node)));
+                if let Some((reserved_t,(occupied_t,occ_node),occ_train)) = occupied.remove(tvd) {
+                    vacant.insert(*tvd, (reserved_t, (occupied_t,occ_node),(t, *node),occ_train));
                 }
             },
             InfrastructureLogEvent::Reserved(tvd,on) if !*on => { 
-                if let Some((res_t, (occ_t, occ_node), (vac_t, vac_node))) = vacant.remove(tvd) {
+                if let Some((res_t, (occ_t, occ_node), (vac_t, vac_node), occ_train)) = vacant.remove(tvd) {
                     if let Some(pos1) = dgraph.mileage.get(&occ_node) {
                         if let Some(pos2) = dgraph.mileage.get(&vac_node) {
                             output.push(BlockGraph {
                                 pos: (pos1.min(*pos2), pos1.max(*pos2)),
                                 reserved: (res_t, t),
                                 occupied: (occ_t, vac_t),
-                                train: 0, // TODO
+                                train: occ_train,
+                                section: *tvd,
                                 info: format!("info"), // TODO
                             });
                         }
@@ -242,25 +368,27 @@ fn plot_blocks(history :&History, dgraph :&DGraph) -> Vec<BlockGraph> {
     }
 
     // Any boxes that are still reserved or occupied should also be painted
-    for (tvd, (reserved_t, (occupied_t, occ_node))) in occupied {
+    for (tvd, (reserved_t, (occupied_t, occ_node), occ_train)) in occupied {
         if let Some(tvd_interval) = tvd_max_pos_interval(dgraph, tvd) {
             output.push(BlockGraph {
                 pos: tvd_interval,
                 reserved: (reserved_t, t),
                 occupied: (occupied_t,t),
-                train: 0,
+                train: occ_train,
+                section: tvd,
                 info: format!("info"), // TODO
             });
         }
     }
-    for (tvd, (reserved_t, (occupied_t, occ_node), (vac_t, vac_node))) in vacant {
+    for (tvd, (reserved_t, (occupied_t, occ_node), (vac_t, vac_node), occ_train)) in vacant {
         if let Some(pos1) = dgraph.mileage.get(&occ_node) {
             if let Some(pos2) = dgraph.mileage.get(&vac_node) {
                 output.push(BlockGraph {
                     pos: (pos1.min(*pos2), pos1.max(*pos2)),
                     reserved: (reserved_t, t),
                     occupied: (occupied_t,vac_t),
-                    train: 0,
+                    train: occ_train,
+                    section: tvd,
                     info: format!("info"), // TODO
                 });
             }
@@ -291,7 +419,7 @@ fn plot_trains(history :&History, dgraph :&DGraph) -> Vec<TrainGraph> {
         let mut edge_x = 0.0;
         let mut t = 0.0;
         let mut current_edge_pos = None;
-        let mut prev_v = 0.0; // TODO allow train v0 != 0
+        let mut prev_v = 0.0; // trains entering at speed start with a zero-length move
         let mut edges_occupied = Vec::new();
         for e in events {
             match e {
@@ -563,3 +691,24 @@ fn truncate_edge_list(e :&mut Vec<((usize, Option<usize>), f64, f64)>, mut l :f6
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn reservation_by_route_and_train() {
+        // Section 1 is first reserved by a crossing route (sections 1 and 5) for train 0,
+        // then by the route with sections 1 and 2, first for train 0 and then for train 1.
+        let reservations = vec![
+            (10.0, 1, Some(0)), (10.0, 5, Some(0)),
+            (20.0, 1, Some(0)), (20.0, 2, Some(0)),
+            (30.0, 2, Some(1)), (40.0, 1, Some(1)), (40.0, 2, Some(1)),
+        ];
+        assert_eq!(route_reserved(&reservations, &[1,2], 0.0, None), Some(20.0));
+        assert_eq!(route_reserved(&reservations, &[1,2], 0.0, Some(1)), Some(40.0));
+        assert_eq!(route_reserved(&reservations, &[1,2], 25.0, None), Some(40.0));
+        assert_eq!(route_reserved(&reservations, &[1,2], 50.0, None), None);
+        assert_eq!(route_reserved(&reservations, &[], 0.0, None), None);
+    }
+}
@@ -0,0 +1,226 @@
+// Train dynamics with tractive effort, running resistance and gradients.
+//
+// The simulator takes a constant acceleration, braking rate and maximum
+// velocity for each train, and does not know about gradients. For vehicles
+// with a physical model, these are replaced by equivalent constant values
+// on level track: the acceleration reaching the same speed in the same
+// time, and the braking rate stopping the train in the same distance.
+// Gradients are given to the simulator as speed restrictions on the
+// sections where they apply (see `gradient_speed`).
+
+use crate::document::model::*;
+
+const G :f64 = 9.81;
+const DT :f64 = 0.5;
+/// Acceleration (m/s^2) below which the train is considered to be at its balancing speed.
+const BALANCING_ACC :f64 = 0.005;
+const MAX_TIME :f64 = 3600.0;
+
+/// Tractive effort (kN) at speed `v` (m/s).
+pub fn tractive_effort(d :&VehicleDynamics, v :f64) -> f64 {
+    interpolate(&d.tractive_effort, v)
+}
+
+/// Braking force (kN) at speed `v` (m/s), if the vehicle has a brake curve.
+pub fn brake_effort(d :&VehicleDynamics, v :f64) -> Option<f64> {
+    if d.brake_effort.is_empty() { None } else { Some(interpolate(&d.brake_effort, v)) }
+}
+
+/// Piecewise linear curve, constant outside the points.
+pub fn interpolate(pts :&[(f32,f32)], v :f64) -> f64 {
+    if pts.is_empty() { return 0.0; }
+    if v <= pts[0].0 as f64 { return pts[0].1 as f64; }
+    for w in pts.windows(2) {
+        let ((v0,f0),(v1,f1)) = ((w[0].0 as f64, w[0].1 as f64), (w[1].0 as f64, w[1].1 as f64));
+        if v <= v1 {
+            if v1 - v0 <= 0.0 { return f1; }
+            return f0 + (f1 - f0) * (v - v0) / (v1 - v0);
+        }
+    }
+    pts[pts.len()-1].1 as f64
+}
+
+/// Running resistance (kN) at speed `v` (m/s).
+pub fn resistance(d :&VehicleDynamics, v :f64) -> f64 {
+    let (a,b,c) = d.davis;
+    a as f64 + b as f64 * v + c as f64 * v * v
+}
+
+/// Acceleration (m/s^2) at full traction, on a gradient (per mille, positive uphill).
+pub fn acceleration(d :&VehicleDynamics, v :f64, gradient :f64) -> f64 {
+    let m = d.mass as f64;
+    let grade = m * G * gradient / 1000.0;
+    (tractive_effort(d,v) - resistance(d,v) - grade) / (m * d.rotating_mass as f64)
+}
+
+/// Deceleration (m/s^2) at full service braking, on a gradient (per mille, positive uphill).
+/// Without a brake curve, the brakes give a constant `max_brk`.
+pub fn deceleration(d :&VehicleDynamics, max_brk :f64, v :f64, gradient :f64) -> f64 {
+    let m = d.mass as f64 * d.rotating_mass as f64;
+    let grade = d.mass as f64 * G * gradient / 1000.0;
+    let brakes = match brake_effort(d,v) {
+        Some(force) => force / m,
+        None => max_brk,
+    };
+    brakes + (resistance(d,v) + grade) / m
+}
+
+/// The model's vehicles, given to the simulation which derives the 
+/// equivalent parameters for each train.
+pub fn vehicles(model :&Model) -> Vec<(usize,Vehicle)> {
+    model.vehicles.iter().map(|(id,v)| (*id, v.clone())).collect()
+}
+
+/// Constant acceleration, braking and maximum velocity equivalent to the
+/// vehicle's physical model, accelerating up a `rise` and braking down 
+/// a `fall` (per mille). The maximum velocity is limited to the balancing 
+/// speed on the rise.
+pub fn equivalent_vehicle(vehicle :&Vehicle, rise :f64, fall :f64) -> Result<Vehicle,String> {
+    let d = match &vehicle.dynamics {
+        Some(d) => d,
+        None => return Ok(vehicle.clone()),
+    };
+
+    // Accelerate from standstill to the maximum or the balancing speed.
+    let v_max = vehicle.max_vel as f64;
+    let (mut v, mut t) = (0.0, 0.0);
+    while v < v_max && t < MAX_TIME {
+        let a = acceleration(d, v, rise);
+        if a < BALANCING_ACC { break; }
+        v = (v + a*DT).min(v_max);
+        t += DT;
+    }
+    if !(v > 0.0) {
+        return Err(format!("Vehicle \"{}\" cannot start on a gradient of {:.1}\u{2030}.", vehicle.name, rise));
+    }
+    let max_acc = v / t;
+
+    // Brake from that speed to standstill.
+    let (mut u, mut s, mut t) = (v, 0.0, 0.0);
+    while u > 0.0 {
+        let b = deceleration(d, vehicle.max_brk as f64, u, -fall);
+        if !(b > 0.0) || t > MAX_TIME {
+            return Err(format!("Vehicle \"{}\" cannot stop on a gradient of -{:.1}\u{2030}.", vehicle.name, fall));
+        }
+        let dt = DT.min(u / b);
+        s += u*dt - 0.5*b*dt*dt;
+        u -= b*dt;
+        t += dt;
+    }
+    let max_brk = v*v / (2.0*s);
+
+    Ok(Vehicle {
+        max_acc: max_acc as _,
+        max_brk: max_brk as _,
+        max_vel: v as _,
+        ..vehicle.clone()
+    })
+}
+
+/// Highest speed (m/s) for the vehicle on a gradient (per mille, positive
+/// uphill), or `None` if the gradient does not slow it down compared to
+/// level track. Uphill, this is the balancing speed. Downhill, the simulator
+/// still brakes at the level-track rate, so the speed is lowered until the
+/// actual braking distance is no longer than the level-track braking 
+/// distance from the maximum velocity.
+pub fn gradient_speed(vehicle :&Vehicle, gradient :f64) -> Result<Option<f64>,String> {
+    if vehicle.dynamics.is_none() { return Ok(None); }
+    let level = equivalent_vehicle(vehicle, 0.0, 0.0)?;
+    let (v_level, b_level) = (level.max_vel as f64, level.max_brk as f64);
+    if gradient > 0.0 {
+        let v = equivalent_vehicle(vehicle, gradient, 0.0)?.max_vel as f64;
+        Ok(if v < v_level { Some(v) } else { None })
+    } else if gradient < 0.0 {
+        let b = equivalent_vehicle(vehicle, 0.0, -gradient)?.max_brk as f64;
+        Ok(if b < b_level { Some(v_level * (b / b_level).sqrt()) } else { None })
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vehicle() -> Vehicle {
+        Vehicle {
+            name: format!("test"),
+            length: 100.0,
+            max_acc: 1.0,
+            max_brk: 0.8,
+            max_vel: 40.0,
+            dynamics: Some(VehicleDynamics {
+                mass: 400.0,
+                rotating_mass: 1.0,
+                tractive_effort: vec![(0.0, 300.0), (20.0, 300.0), (40.0, 150.0)],
+                brake_effort: vec![],
+                davis: (4.0, 0.0, 0.0),
+            }),
+        }
+    }
+
+    #[test]
+    pub fn interpolation() {
+        let pts = [(0.0, 300.0), (20.0, 300.0), (40.0, 100.0)];
+        assert_eq!(interpolate(&[], 10.0), 0.0);
+        assert_eq!(interpolate(&pts, -5.0), 300.0);
+        assert_eq!(interpolate(&pts, 10.0), 300.0);
+        assert_eq!(interpolate(&pts, 30.0), 200.0);
+        assert_eq!(interpolate(&pts, 50.0), 100.0);
+        // A step in the curve.
+        assert_eq!(interpolate(&[(0.0, 10.0), (5.0, 10.0), (5.0, 20.0)], 5.0), 20.0);
+    }
+
+    #[test]
+    pub fn without_dynamics() {
+        let v = Vehicle { dynamics: None, ..vehicle() };
+        let eq = equivalent_vehicle(&v, 10.0, 10.0).unwrap();
+        assert_eq!((eq.max_acc, eq.max_brk, eq.max_vel), (v.max_acc, v.max_brk, v.max_vel));
+    }
+
+    #[test]
+    pub fn level_track() {
+        let v = vehicle();
+        let eq = equivalent_vehicle(&v, 0.0, 0.0).unwrap();
+        // Reaches the maximum velocity, slower than the starting acceleration.
+        assert_eq!(eq.max_vel, 40.0);
+        let a0 = acceleration(v.dynamics.as_ref().unwrap(), 0.0, 0.0);
+        assert!(eq.max_acc > 0.0 && (eq.max_acc as f64) < a0);
+        // Running resistance helps the brakes.
+        assert!(eq.max_brk > v.max_brk);
+    }
+
+    #[test]
+    pub fn gradients() {
+        let v = vehicle();
+        let level = equivalent_vehicle(&v, 0.0, 0.0).unwrap();
+        let uphill = equivalent_vehicle(&v, 20.0, 0.0).unwrap();
+        let downhill = equivalent_vehicle(&v, 0.0, 20.0).unwrap();
+        assert!(uphill.max_acc < level.max_acc);
+        assert_eq!(uphill.max_brk, level.max_brk);
+        assert_eq!(downhill.max_acc, level.max_acc);
+        assert!(downhill.max_brk < level.max_brk);
+
+        // On 40 per mille, the tractive effort equals the running resistance
+        // and the grade force, 4 + 157 kN, at about 38 m/s.
+        let steep = equivalent_vehicle(&v, 40.0, 0.0).unwrap();
+        assert!(steep.max_vel > 37.5 && steep.max_vel < 38.5);
+
+        assert!(equivalent_vehicle(&v, 80.0, 0.0).is_err());
+        assert!(equivalent_vehicle(&v, 0.0, 250.0).is_err());
+    }
+
+    #[test]
+    pub fn gradient_speeds() {
+        let v = vehicle();
+        assert_eq!(gradient_speed(&v, 0.0).unwrap(), None);
+        assert_eq!(gradient_speed(&v, 10.0).unwrap(), None);
+        let uphill = gradient_speed(&v, 40.0).unwrap().unwrap();
+        assert!(uphill > 37.5 && uphill < 38.5);
+        let downhill = gradient_speed(&v, -20.0).unwrap().unwrap();
+        assert!(downhill < 40.0);
+        assert!(gradient_speed(&v, -40.0).unwrap().unwrap() < downhill);
+        assert!(gradient_speed(&v, 80.0).is_err());
+        assert_eq!(gradient_speed(&Vehicle { dynamics: None, ..v }, 40.0).unwrap(), None);
+    }
+}
@@ -1,8 +1,13 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use rolling::input::staticinfrastructure as rolling_inf;
 pub use rolling::output::history::History;
+use rolling::output::history::TrainLogEvent;
 
 use crate::document::model::*;
 use crate::document::interlocking::*;
+use crate::document::dgraph::DGraph;
+use crate::document::dynamics;
 
 pub fn convert_vehicle(vehicle :&Vehicle) -> rolling::railway::dynamics::TrainParams {
     rolling::railway::dynamics::TrainParams {
@@ -13,73 +18,352 @@ pub fn convert_vehicle(vehicle :&Vehicle) -> rolling::railway::dynamics::TrainPa
     }
 }
 
+fn get_vehicle(vehicles :&[(usize,Vehicle)], id :usize) -> Vehicle {
+    vehicles.iter().find(|(i,_)| *i == id).map(|(_,v)| v).cloned().unwrap_or(Vehicle {
+        name :format!("Default train"),
+        length: 210.0,
+        max_acc: 0.95,
+        max_brk: 0.75,
+        max_vel: 180.0 / 3.6, // 180 km/h in m/s
+        dynamics: None,
+    })
+}
+
+/// The first speed limit (m/s) on a route path.
+fn first_speed_limit(inf :&rolling_inf::StaticInfrastructure, 
+                     path :&[(rolling_inf::NodeId, rolling_inf::NodeId)]) -> Option<f64> {
+    path.iter().flat_map(|(a,_)| inf.nodes[*a].objects.iter())
+        .filter_map(|obj| match inf.objects[*obj] {
+            rolling_inf::StaticObject::SpeedRestriction(v) => Some(v),
+            _ => None,
+        }).next()
+}
+
+/// Speed (m/s) of a train entering on the given route. Trains entering
+/// through a model boundary run at line speed unless another entry speed
+/// is given, and trains starting inside the model start from standstill.
+fn entry_velocity(inf :&rolling_inf::StaticInfrastructure, route :&RouteInfo,
+                  entry :Option<&TrainEntry>, max_vel :f64) -> f64 {
+    if !matches!(route.route.entry, rolling_inf::RouteEntryExit::Boundary(Some(_))) { return 0.0; }
+    let v = match entry.map(|e| e.entry_speed).unwrap_or_default() {
+        EntrySpeed::Standstill => 0.0,
+        EntrySpeed::LineSpeed => first_speed_limit(inf, &route.path).unwrap_or(std::f64::INFINITY),
+        EntrySpeed::Speed(kmh) => kmh / 3.6,
+    };
+    v.min(max_vel)
+}
+
+/// Adds a run-in track of the given length outside a model boundary node,
+/// and returns the boundary node at its far end.
+fn add_run_in(inf :&mut rolling_inf::StaticInfrastructure, boundary :rolling_inf::NodeId, 
+              length :f64) -> rolling_inf::NodeId {
+    let (start, inward) = (inf.nodes.len(), inf.nodes.len() + 1);
+    inf.nodes.push(rolling_inf::Node { other_node: inward,
+        edges: rolling_inf::Edges::ModelBoundary, objects: Default::default() });
+    inf.nodes.push(rolling_inf::Node { other_node: start,
+        edges: rolling_inf::Edges::Single(boundary, length), objects: Default::default() });
+    start
+}
+
+/// Replaces the part of a train's log before it enters the model by waiting.
+/// A zero-length move after the first edge in the model keeps the velocity
+/// the train had when it passed the model boundary.
+fn skip_run_in(log :&mut Vec<TrainLogEvent>, model_nodes :usize) {
+    use rolling::railway::dynamics::DistanceVelocity;
+    let first = match log.iter().position(|ev| matches!(ev, TrainLogEvent::Edge(a,_) if *a < model_nodes)) {
+        Some(i) => i,
+        None => return,
+    };
+    let mut dt = 0.0;
+    let mut last_move = None;
+    for ev in log.drain(..first) {
+        match ev {
+            TrainLogEvent::Wait(w) => { dt += w; },
+            TrainLogEvent::Move(w, action, DistanceVelocity { v, .. }) => {
+                dt += w;
+                last_move = Some((action, v));
+            },
+            _ => {},
+        }
+    }
+    if let Some((action, v)) = last_move {
+        log.insert(1, TrainLogEvent::Move(0.0, action, DistanceVelocity { dx: 0.0, v }));
+    }
+    log.insert(0, TrainLogEvent::Wait(dt));
+}
+
+/// Removes the first `dt` seconds of a log, as far as they are spent waiting.
+fn skip_time<E>(log :&mut Vec<E>, mut dt :f64, wait :impl Fn(&mut E) -> Option<&mut f64>) {
+    let mut skip = 0;
+    while dt > 0.0 && skip < log.len() {
+        match wait(&mut log[skip]) {
+            Some(w) if *w <= dt => { dt -= *w; skip += 1; },
+            Some(w) => { *w -= dt; dt = 0.0; },
+            None => break,
+        }
+    }
+    log.drain(..skip);
+}
+
 pub type RouteRefs = Vec<(f32,usize)>;
+
+/// Run the simulator. The vehicles' physical models are replaced by 
+/// equivalent parameters on level track, with the gradients applied as
+/// speed restrictions.
+///
+/// The simulator can only start trains at standstill, so a train entering
+/// at speed is started on a run-in track outside the model boundary, early 
+/// enough to pass the boundary at its entry speed at the command time. The
+/// run-in is removed from the train's log afterwards.
 pub fn get_history<'a>(vehicles :&[(usize,Vehicle)], 
-                   inf :&rolling_inf::StaticInfrastructure, 
+                   dgraph :&DGraph,
                    il :&Interlocking,
-                   commands :&[(usize, (f64, Command))]) -> Result<(History, RouteRefs) , String> {
+                   commands :&[(usize, (f64, Command))],
+                   trains :&im::HashMap<usize, TrainEntry>) -> Result<(History, RouteRefs) , String> {
+    use rolling::input::dispatch::DispatchAction;
+    use rolling::output::history::InfrastructureLogEvent;
 
-    // infrastructure and routes are already prepared by the dgraph module
-    // we only need to convert commands to the rolling dispatch structs
-    // and back from rolling history to glrail history
+    // The vehicle of each train, in command order.
+    let train_vehicles = commands.iter().filter_map(|(_,(_,c))| match c {
+        Command::Train(vehicle, routespec) if il.find_route(routespec).is_some() => 
+            Some(get_vehicle(vehicles, *vehicle)),
+        _ => None,
+    }).collect::<Vec<_>>();
 
-    use rolling::input::dispatch::DispatchAction;
+    let mut inf = match gradient_infrastructure(dgraph, &train_vehicles) {
+        Some(inf) => Cow::Owned(inf),
+        None => Cow::Borrowed(&dgraph.rolling_inf),
+    };
+    let mut routes :HashMap<usize,rolling_inf::Route> = 
+        il.routes.iter().map(|r| r.route.clone()).enumerate().collect();
 
+    // Simulation start time, train index and action for each command.
+    let mut actions = Vec::new();
     let mut route_refs = Vec::new();
-    let mut dispatch = Vec::new();
-    let mut t0 = 0.0;
-    let mut train_no = 0;
-    for (cmd_id,(t,c)) in commands {
-        if *t > t0 {
-            dispatch.push(DispatchAction::Wait(Some((t-t0) as _ )));
-            t0 = *t;
-        }
-
+    let mut run_in = Vec::new();
+    for (cmd_id,(t,c)) in commands.iter() {
         match c {
             Command::Route(routespec) => {
                 if let Some(route_idx) = il.find_route(routespec) {
-                    dispatch.push(DispatchAction::Route(*route_idx));
+                    actions.push((*t, None, DispatchAction::Route(*route_idx)));
                     route_refs.push((*t as f32, *route_idx));
                 }
             }
             Command::Train(vehicle, routespec) => {
                 if let Some(route_idx) = il.find_route(routespec) {
-                    // get train params
-                    let vehicle = vehicles.iter().find(|(i,v)| i == vehicle).map(|(i,v)| v).cloned().unwrap_or(Vehicle {
-                        name :format!("Default train"),
-                        length: 210.0,
-                        max_acc: 0.95,
-                        max_brk: 0.75,
-                        max_vel: 180.0 / 3.6, // 180 km/h in m/s
-                    });
-
+                    let vehicle = dynamics::equivalent_vehicle(&get_vehicle(vehicles, *vehicle), 0.0, 0.0)?;
                     let train_params = convert_vehicle(&vehicle);
 
-                    // just make some name for now
-                    let name = format!("train{}", train_no+1);
-                    train_no += 1;
+                    let train_no = run_in.len();
+                    let name = trains.get(cmd_id).and_then(|e| e.name.clone())
+                        .unwrap_or_else(|| format!("train{}", train_no + 1));
 
-                    dispatch.push(DispatchAction::Train(name, train_params, *route_idx));
+                    let route = &il.routes[*route_idx];
+                    let v = entry_velocity(&inf, route, trains.get(cmd_id), vehicle.max_vel as f64);
+                    let acc = vehicle.max_acc as f64;
+                    let (start_t, sim_route) = match route.route.entry {
+                        rolling_inf::RouteEntryExit::Boundary(Some(boundary)) if v > 0.0 && acc > 0.0 => {
+                            let length = v * v / (2.0 * acc);
+                            let mut sim_route = route.route.clone();
+                            sim_route.entry = rolling_inf::RouteEntryExit::Boundary(
+                                Some(add_run_in(inf.to_mut(), boundary, length)));
+                            sim_route.length += length;
+                            let sim_route_idx = routes.len();
+                            routes.insert(sim_route_idx, sim_route);
+                            (*t - v / acc, sim_route_idx)
+                        },
+                        _ => (*t, *route_idx),
+                    };
+
+                    run_in.push(sim_route != *route_idx);
+                    actions.push((start_t, Some(train_no), DispatchAction::Train(name, train_params, sim_route)));
                     route_refs.push((*t as f32, *route_idx));
                 }
             },
         }
     }
 
-    //println!("Dispatch converted: {:#?}", dispatch);
-    //println!(" Running rolling with");
-    //println!("infrastructuer : {:?}", inf);
-    //println!("routes : {:?}", routes);
+    // Run-ins starting before time zero delay the whole simulation.
+    let lead = actions.iter().map(|(t,_,_)| -*t).fold(0.0, f64::max);
+    actions.sort_by(|(a,_,_),(b,_,_)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
 
-    // TODO don't convert on the fly?
-    //println!("Starting rolling");
-    let history = rolling::evaluate_plan(inf,
-                                         &il.routes.iter().map(|r| r.route.clone()).enumerate().collect(),
-                                         &rolling::input::dispatch::Dispatch { actions: dispatch }, None);
+    let mut dispatch = Vec::new();
+    let mut creation_order = Vec::new();
+    let mut t0 = 0.0;
+    for (t, train, action) in actions {
+        let t = t + lead;
+        if t > t0 {
+            dispatch.push(DispatchAction::Wait(Some((t-t0) as _ )));
+            t0 = t;
+        }
+        creation_order.extend(train);
+        dispatch.push(action);
+    }
 
-    //println!("History output: {:?}", history);
-    // TODO Convert back? Or just keep it like this
-    //unimplemented!();
+    let mut history = rolling::evaluate_plan(&inf, &routes,
+                                             &rolling::input::dispatch::Dispatch { actions: dispatch }, None);
 
-    Ok((history,route_refs))
+    // Put the trains back in command order and remove the run-ins.
+    let mut created = history.trains.drain(..).zip(creation_order.iter())
+        .map(|(train, idx)| (*idx, train)).collect::<Vec<_>>();
+    created.sort_by_key(|(idx,_)| *idx);
+    history.trains = created.into_iter().map(|(_,train)| train).collect();
+    for ev in history.inf.iter_mut() {
+        match ev {
+            InfrastructureLogEvent::Occupied(_,_,_,train) => { 
+                if let Some(idx) = creation_order.get(*train) { *train = *idx; }
+            },
+            _ => {},
+        }
+    }
+    for ((_,_,log),run_in) in history.trains.iter_mut().zip(run_in.iter()) {
+        if *run_in { skip_run_in(log, dgraph.rolling_inf.nodes.len()); }
+        skip_time(log, lead, |ev| match ev { TrainLogEvent::Wait(dt) => Some(dt), _ => None });
+    }
+    skip_time(&mut history.inf, lead, |ev| match ev { InfrastructureLogEvent::Wait(dt) => Some(dt), _ => None });
+
+    // Fail if a train has entered a gradient it cannot start on or stop on.
+    for ((_,_,log),vehicle) in history.trains.iter().zip(train_vehicles.iter()) {
+        for ev in log.iter() {
+            if let TrainLogEvent::Edge(a,_) = ev {
+                for section in dgraph.gradient_sections.iter().filter(|s| s.start == *a) {
+                    dynamics::gradient_speed(vehicle, section.gradient)?;
+                }
+            }
+        }
+    }
+
+    Ok((history, route_refs))
+}
+
+/// The infrastructure with speed restrictions on the gradient sections,
+/// for the trains' vehicles that have a physical model, or `None` if no 
+/// speed restrictions are needed. The restrictions are shared by all trains, 
+/// so each section is restricted to the lowest speed of any of the vehicles. 
+/// The speed limit from before the section is restored where it ends.
+fn gradient_infrastructure(dgraph :&DGraph, train_vehicles :&[Vehicle]) 
+    -> Option<rolling_inf::StaticInfrastructure> {
+    let dynamic = train_vehicles.iter().filter(|v| v.dynamics.is_some()).collect::<Vec<_>>();
+    if dynamic.is_empty() || dgraph.gradient_sections.is_empty() { return None; }
+    let no_limit = train_vehicles.iter().map(|v| v.max_vel as f64).fold(0.0, f64::max);
+
+    let mut starts = HashMap::new();
+    let mut ends = HashMap::new();
+    for section in dgraph.gradient_sections.iter() {
+        // Vehicles that cannot run on the gradient at all are reported after the simulation.
+        let limit = dynamic.iter()
+            .filter_map(|v| dynamics::gradient_speed(v, section.gradient).ok().and_then(|v| v))
+            .fold(std::f64::INFINITY, f64::min);
+        let line_speed = section.line_speed.unwrap_or(no_limit);
+        if !(limit < line_speed) { continue; }
+        starts.insert(section.start, limit);
+        ends.insert(section.end, line_speed);
+    }
+    if starts.is_empty() { return None; }
+
+    let mut inf = dgraph.rolling_inf.clone();
+    let ends = ends.into_iter().filter(|(node,_)| !starts.contains_key(node)).collect::<Vec<_>>();
+    for (node,v) in ends.into_iter().chain(starts.into_iter()) {
+        let obj = inf.objects.len();
+        inf.objects.push(rolling_inf::StaticObject::SpeedRestriction(v));
+        inf.nodes[node].objects.push(obj);
+    }
+    Some(inf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm as glm;
+    use crate::document::{topology, interlocking, dgraph::DGraphBuilder};
+
+    /// A straight 3 km line from (0,0) to (3,0), with the given gradient
+    /// (per mille) on each of its 1 km segments.
+    fn line(gradients :[f64;3]) -> (DGraph, Interlocking) {
+        let mut m = Model::empty();
+        for (x,g) in gradients.iter().enumerate() {
+            let seg = (glm::vec2(x as i32, 0), glm::vec2(x as i32 + 1, 0));
+            m.linesegs.insert(seg);
+            if *g != 0.0 { m.gradients.insert(seg, *g); }
+        }
+        m.track_lengths.insert((glm::vec2(0,0), glm::vec2(1,0)), 3000.0);
+        let dgraph = DGraphBuilder::convert(&topology::convert(&m, 50.0).unwrap()).unwrap();
+        let il = interlocking::calc(&dgraph, &Default::default()).unwrap();
+        (dgraph, il)
+    }
+
+    /// A heavy train, which climbs 40 per mille at about 28 m/s.
+    fn vehicles() -> Vec<(usize,Vehicle)> {
+        vec![(0, Vehicle {
+            name: format!("freight"),
+            length: 100.0,
+            max_acc: 1.0,
+            max_brk: 0.8,
+            max_vel: 40.0,
+            dynamics: Some(VehicleDynamics {
+                mass: 400.0,
+                rotating_mass: 1.0,
+                tractive_effort: vec![(0.0, 300.0), (10.0, 300.0), (40.0, 75.0)],
+                brake_effort: vec![],
+                davis: (4.0, 0.0, 0.0),
+            }),
+        })]
+    }
+
+    /// Time the train spends moving through the line.
+    fn running_time(gradients :[f64;3]) -> f64 {
+        let (dgraph, il) = line(gradients);
+        let route = RouteSpec { from: Ref::Node(glm::vec2(0,0)), to: Ref::Node(glm::vec2(3,0)), alternative: 0 };
+        let commands = vec![(0, (0.0, Command::Train(0, route)))];
+        let (history,_) = get_history(&vehicles(), &dgraph, &il, &commands, &Default::default()).unwrap();
+        assert_eq!(history.trains.len(), 1);
+        history.trains[0].2.iter().map(|ev| match ev {
+            TrainLogEvent::Move(dt,_,_) => *dt,
+            _ => 0.0,
+        }).sum()
+    }
+
+    #[test]
+    pub fn partial_incline() {
+        let level = running_time([0.0, 0.0, 0.0]);
+        let partial = running_time([0.0, 40.0, 0.0]);
+        let incline = running_time([40.0, 40.0, 40.0]);
+        assert!(level < partial);
+        assert!(partial < incline);
+        assert!(level < running_time([0.0, -40.0, 0.0]));
+    }
+
+    #[test]
+    pub fn entry_at_line_speed() {
+        let (dgraph, il) = line([0.0, 0.0, 0.0]);
+        let vehicles = vec![(0, Vehicle {
+            name: format!("passenger"),
+            length: 100.0,
+            max_acc: 1.0,
+            max_brk: 0.8,
+            max_vel: 20.0,
+            dynamics: None,
+        })];
+        let route = RouteSpec { from: Ref::Node(glm::vec2(0,0)), to: Ref::Node(glm::vec2(3,0)), alternative: 0 };
+        let commands = vec![(0, (0.0, Command::Train(0, route)))];
+
+        // Time before the train first moves, and its velocity then.
+        let entry = |trains :im::HashMap<usize,TrainEntry>| {
+            let (history,_) = get_history(&vehicles, &dgraph, &il, &commands, &trains).unwrap();
+            let log = &history.trains[0].2;
+            let start :f64 = log.iter().take_while(|ev| !matches!(ev, TrainLogEvent::Move(_,_,_)))
+                .map(|ev| match ev { TrainLogEvent::Wait(dt) => *dt, _ => 0.0 }).sum();
+            let v = log.iter().find_map(|ev| match ev { TrainLogEvent::Move(_,_,dv) => Some(dv.v), _ => None });
+            (start, v.unwrap())
+        };
+
+        let (start, v) = entry(Default::default());
+        assert!(start.abs() < 1e-6);
+        assert!((v - 20.0).abs() < 1e-3);
+
+        let standstill = TrainEntry { name: None, entry_speed: EntrySpeed::Standstill };
+        let (start, v) = entry(im::HashMap::unit(0, standstill));
+        assert!(start.abs() < 1e-6);
+        assert!(v < 20.0 - 1e-3);
+    }
 }
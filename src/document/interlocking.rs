@@ -1,7 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use rolling::input::staticinfrastructure as rolling_inf;
 use crate::document::model::*;
 use crate::document::dgraph::*;
+use crate::document::issues::Issue;
 
 #[derive(Debug)]
 pub struct Interlocking {
@@ -10,6 +11,38 @@ pub struct Interlocking {
     pub boundary_out_routes: HashMap<Pt, Vec<usize>>,
     pub signal_routes: HashMap<PtA, Vec<usize>>,
     pub alternatives :HashMap<(Ref,Ref), Vec<usize>>,
+    pub route_issues :Vec<Issue>,
+    /// Conflicting route pairs, stored in both orders.
+    pub conflicts :HashMap<(usize,usize), RouteConflict>,
+}
+
+/// The reason two routes cannot be set at the same time. If several
+/// reasons apply, the first one in this list is used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RouteConflict {
+    /// The routes pass the same track in opposite directions.
+    Opposing,
+    /// The routes need a switch in different positions.
+    SwitchPosition,
+    /// The routes share a detection section, but no track (e.g. a diamond crossing).
+    Crossing,
+    /// The routes share a detection section.
+    SharedSection,
+    /// The overlap of one route needs a detection section or switch position
+    /// used by the other route, for all of its alternative overlaps.
+    Overlap,
+}
+
+impl RouteConflict {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RouteConflict::Opposing => "opposing",
+            RouteConflict::SwitchPosition => "switch position",
+            RouteConflict::Crossing => "crossing",
+            RouteConflict::SharedSection => "shared section",
+            RouteConflict::Overlap => "overlap",
+        }
+    }
 }
 
 impl Interlocking {
@@ -21,6 +54,10 @@ impl Interlocking {
         }
     }
 
+    pub fn conflict(&self, a :usize, b :usize) -> Option<RouteConflict> {
+        self.conflicts.get(&(a,b)).cloned()
+    }
+
     pub fn find_route(&self, spec :&RouteSpec) -> Option<&usize> {
         let alternatives = self.alternatives.get(&(spec.from,spec.to))?;
         alternatives.get(spec.alternative.min(alternatives.len()))
@@ -33,6 +70,7 @@ pub struct RouteInfo {
     pub route :rolling_inf::Route,
     pub id :RouteSpec,
     pub path :Vec<(rolling_inf::NodeId, rolling_inf::NodeId)>,
+    pub flank :route_finder::FlankProtection,
 }
 
 impl RouteInfo {
@@ -46,17 +84,24 @@ impl RouteInfo {
 }
 
 
-pub fn calc(dgraph :&DGraph) -> Interlocking {
+pub fn calc(dgraph :&DGraph, settings :&InterlockingSettings) -> Result<Interlocking, Issue> {
+    let config = route_finder::Config {
+        section_tolerance: settings.section_tolerance,
+        route_minimum_length: settings.route_minimum_length,
+        overlap_length: settings.overlap_length,
+        overlap_timeout: settings.overlap_timeout,
+        flank_protection: settings.flank_protection,
+    };
     let (routes,route_issues) = 
-        route_finder::find_routes(Default::default(), &dgraph.rolling_inf)
-        .expect("interlocking route finder failed");
+        route_finder::find_routes(config, &dgraph.rolling_inf)
+        .map_err(|e| match e { route_finder::ConvertRouteError::String(s) => Issue::RouteFinder(s) })?;
 
     let mut boundary_routes = HashMap::new();
     let mut boundary_out_routes = HashMap::new();
     let mut signal_routes = HashMap::new();
     let mut route_info = Vec::new();
     let mut alternatives : HashMap<(Ref,Ref), Vec<usize>> = HashMap::new();
-    for (route_idx, (route,path)) in routes.into_iter().enumerate() {
+    for (route_idx, (route,path,flank)) in routes.into_iter().enumerate() {
         let from = match route.entry {
             rolling_inf::RouteEntryExit::Boundary(Some(boundary)) => {
                 // Boundary is a NodeId, which should be tied to a Pt in the Dgraph
@@ -96,12 +141,176 @@ pub fn calc(dgraph :&DGraph) -> Interlocking {
         alternative_vec.push(route_idx);
         let alternative = alternative_vec.len()-1;
 
-        route_info.push(RouteInfo { route, id: RouteSpec { from, to, alternative }, path });
+        route_info.push(RouteInfo { route, id: RouteSpec { from, to, alternative }, path, flank });
     }
 
 
+    let mut issues = Vec::new();
+    for issue in route_issues {
+        let issue = convert_route_issue(dgraph, issue);
+        if !issues.contains(&issue) { issues.push(issue); }
+    }
+
+    let conflicts = route_conflicts(&route_info);
     let interlocking = Interlocking { routes: route_info, 
-        boundary_routes, boundary_out_routes, signal_routes, alternatives };
+        boundary_routes, boundary_out_routes, signal_routes, alternatives,
+        route_issues: issues, conflicts };
+
+    Ok(interlocking)
+}
+
+fn route_conflicts(routes :&[RouteInfo]) -> HashMap<(usize,usize), RouteConflict> {
+    // Path edges are (a,b) where b is the node facing back towards a, 
+    // so the same track traversed in the other direction is (b,a).
+    let reverse = |(a,b) :&(rolling_inf::NodeId, rolling_inf::NodeId)| (*b,*a);
+
+    let options = routes.iter().map(|r| resource_options(&r.route)).collect::<Vec<_>>();
+    let mut conflicts = HashMap::new();
+    for (i,a) in routes.iter().enumerate() {
+        let a_edges = a.path.iter().cloned().collect::<HashSet<_>>();
+        for (j,b) in routes.iter().enumerate().skip(i+1) {
+            let opposing = b.path.iter().any(|e| a_edges.contains(&reverse(e)));
+            let switch_position = a.route.resources.switch_positions.iter().any(|(sw_a,pos_a)| 
+                b.route.resources.switch_positions.iter().any(|(sw_b,pos_b)| sw_a == sw_b && pos_a != pos_b));
+            let shared_section = a.route.resources.sections.iter()
+                .any(|s| b.route.resources.sections.contains(s));
+            let shared_track = b.path.iter().any(|e| a_edges.contains(e) || a_edges.contains(&reverse(e)));
+            let overlap = !options[i].iter().any(|(sections_a, switches_a)| 
+                options[j].iter().any(|(sections_b, switches_b)| 
+                    !sections_a.iter().any(|s| sections_b.contains(s)) &&
+                    !switches_a.iter().any(|(sw_a,pos_a)| switches_b.iter().any(|(sw_b,pos_b)| 
+                        sw_a == sw_b && pos_a != pos_b))));
+
+            let conflict = if opposing { Some(RouteConflict::Opposing) }
+                else if switch_position { Some(RouteConflict::SwitchPosition) }
+                else if shared_section && !shared_track { Some(RouteConflict::Crossing) }
+                else if shared_section { Some(RouteConflict::SharedSection) }
+                else if overlap { Some(RouteConflict::Overlap) }
+                else { None };
+
+            if let Some(c) = conflict {
+                conflicts.insert((i,j), c);
+                conflicts.insert((j,i), c);
+            }
+        }
+    }
+    conflicts
+}
+
+type RouteResourceSet = (Vec<rolling_inf::ObjectId>, Vec<(rolling_inf::ObjectId, rolling_inf::SwitchPosition)>);
 
-    interlocking
+/// Detection sections and switch positions used by a route together with 
+/// each of its alternative overlaps, or by the route alone if it has none.
+fn resource_options(route :&rolling_inf::Route) -> Vec<RouteResourceSet> {
+    let path :RouteResourceSet = (route.resources.sections.iter().cloned().collect(),
+                                  route.resources.switch_positions.iter().cloned().collect());
+    if route.overlaps.is_empty() { return vec![path]; }
+    route.overlaps.iter().map(|o| {
+        let (mut sections, mut switches) = path.clone();
+        sections.extend(o.sections.iter().cloned());
+        switches.extend(o.switch_positions.iter().cloned());
+        (sections, switches)
+    }).collect()
+}
+
+pub fn entry_exit_ref(dgraph :&DGraph, e :&rolling_inf::RouteEntryExit) -> Option<Ref> {
+    match e {
+        rolling_inf::RouteEntryExit::Boundary(Some(node)) =>
+            dgraph.node_ids.get_by_left(node).map(|pt| Ref::Node(*pt)),
+        rolling_inf::RouteEntryExit::Signal(signal) |
+        rolling_inf::RouteEntryExit::SignalTrigger { signal, .. } =>
+            dgraph.object_ids.get_by_left(signal).map(|pta| Ref::Object(*pta)),
+        _ => None,
+    }
+}
+
+/// Locate a detection section by one of its delimiting detectors or boundaries.
+pub fn section_ref(dgraph :&DGraph, tvd :rolling_inf::ObjectId) -> Option<Ref> {
+    for node in dgraph.tvd_entry_nodes.get(&tvd)? {
+        let other = dgraph.rolling_inf.nodes[*node].other_node;
+        for n in &[*node, other] {
+            if let Some(pta) = dgraph.detector_ids.get_by_left(n) { return Some(Ref::Object(*pta)); }
+            if let Some(pt) = dgraph.node_ids.get_by_left(n) { return Some(Ref::Node(*pt)); }
+        }
+    }
+    None
+}
+
+fn convert_route_issue(dgraph :&DGraph, issue :route_finder::ConvertRouteIssue) -> Issue {
+    use route_finder::ConvertRouteIssue;
+    match issue {
+        ConvertRouteIssue::NoBoundaries => Issue::NoBoundaries,
+        ConvertRouteIssue::StateConversionFailed(a,b) =>
+            Issue::RouteConversion(entry_exit_ref(dgraph, &a), entry_exit_ref(dgraph, &b)),
+        ConvertRouteIssue::ExitedUnenteredSection(a,tvd) =>
+            Issue::ExitedUnenteredSection(entry_exit_ref(dgraph, &a), section_ref(dgraph, tvd)),
+        ConvertRouteIssue::RouteTooShort(a,b) =>
+            Issue::RouteTooShort(entry_exit_ref(dgraph, &a), entry_exit_ref(dgraph, &b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm as glm;
+
+    fn route(path :Vec<(usize,usize)>, sections :Vec<usize>, 
+             switches :Vec<(usize, rolling_inf::SwitchPosition)>,
+             overlaps :Vec<(Vec<usize>, Vec<(usize, rolling_inf::SwitchPosition)>)>) -> RouteInfo {
+        RouteInfo {
+            route: rolling_inf::Route {
+                entry: rolling_inf::RouteEntryExit::Boundary(None),
+                exit: rolling_inf::RouteEntryExit::Boundary(None),
+                length: 100.0,
+                resources: rolling_inf::RouteResources {
+                    sections: sections.into_iter().collect(),
+                    switch_positions: switches.into_iter().collect(),
+                    releases: Default::default(),
+                },
+                overlaps: overlaps.into_iter().map(|(sections, switches)| rolling_inf::Overlap {
+                    name: None,
+                    sections: sections.into_iter().collect(),
+                    switch_positions: switches.into_iter().collect(),
+                    timeout: None,
+                }).collect(),
+                swinging_overlap: false,
+            },
+            id: RouteSpec { from: Ref::Node(glm::vec2(0,0)), to: Ref::Node(glm::vec2(1,0)), alternative: 0 },
+            path,
+            flank: Default::default(),
+        }
+    }
+
+    #[test]
+    pub fn conflict_kinds() {
+        use rolling_inf::SwitchPosition::*;
+        let routes = vec![
+            route(vec![(0,1),(2,3)], vec![10], vec![(20,Left)], vec![]),
+            route(vec![(3,2)], vec![10], vec![], vec![]),
+            route(vec![(4,5)], vec![11], vec![(20,Right)], vec![]),
+            route(vec![(6,7)], vec![10], vec![], vec![]),
+            route(vec![(8,9)], vec![12], vec![], vec![(vec![13], vec![])]),
+            route(vec![(10,11)], vec![13], vec![], vec![]),
+            route(vec![(12,13)], vec![14], vec![], vec![(vec![13], vec![]), (vec![15], vec![])]),
+        ];
+        let conflicts = route_conflicts(&routes);
+        assert_eq!(conflicts.get(&(0,1)), Some(&RouteConflict::Opposing));
+        assert_eq!(conflicts.get(&(0,2)), Some(&RouteConflict::SwitchPosition));
+        assert_eq!(conflicts.get(&(0,3)), Some(&RouteConflict::Crossing));
+        assert_eq!(conflicts.get(&(1,0)), Some(&RouteConflict::Opposing));
+        assert_eq!(conflicts.get(&(4,5)), Some(&RouteConflict::Overlap));
+        assert_eq!(conflicts.get(&(5,4)), Some(&RouteConflict::Overlap));
+        // Only one of the alternative overlaps is blocked.
+        assert_eq!(conflicts.get(&(5,6)), None);
+        assert_eq!(conflicts.get(&(2,5)), None);
+    }
+
+    #[test]
+    pub fn shared_section_on_shared_track() {
+        let routes = vec![
+            route(vec![(0,1),(2,3)], vec![10], vec![], vec![]),
+            route(vec![(2,3),(4,5)], vec![10], vec![], vec![]),
+        ];
+        assert_eq!(route_conflicts(&routes).get(&(0,1)), Some(&RouteConflict::SharedSection));
+    }
 }
@@ -0,0 +1,140 @@
+use crate::document::model::*;
+use crate::document::infview::unround_coord;
+use nalgebra_glm as glm;
+
+/// Problems found while converting the drawn model into topology, dgraph
+/// and interlocking, and while simulating its dispatches. Issues refer back to the model location where possible,
+/// so that they can be shown on the infrastructure canvas.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// Track pieces meet in a way that is not recognized as a model boundary,
+    /// continuation, switch or crossing.
+    UnrecognizedNode(Pt),
+    /// Node set to the error type in the node data.
+    ErrorNode(Pt),
+    /// Node data does not fit the shape of the node (e.g. a crossing type on
+    /// a switch), so it is ignored.
+    NodeDataMismatch(Pt, NDType),
+    /// Two track pieces cross each other without sharing a node.
+    OverlappingSegments((Pt,Pt),(Pt,Pt)),
+    /// A track end could not be connected to a node.
+    UnconnectedTrack(Pt),
+    /// Object is not placed along a track.
+    ObjectOutsideTrack(PtA),
+    /// Object position could not be found in the dgraph (e.g. placed on a node).
+    ObjectPlacement(PtA),
+    /// Train detection sections could not be derived from detectors.
+    DetectionSections(String),
+    /// Route finding failed.
+    RouteFinder(String),
+    /// No model boundaries to start searching for routes from.
+    NoBoundaries,
+    /// Route entry or exit could not be converted (from, to).
+    RouteConversion(Option<Ref>, Option<Ref>),
+    /// Route passes out of a detection section it did not enter (route entry, section).
+    ExitedUnenteredSection(Option<Ref>, Option<Ref>),
+    /// Route is shorter than the minimum route length (from, to).
+    RouteTooShort(Option<Ref>, Option<Ref>),
+    /// Line segments of the same track have different pinned lengths.
+    ConflictingTrackLength((Pt,Pt),(Pt,Pt)),
+    /// Pinned mileage does not fit with the other pins and the track lengths
+    /// (location, pinned, computed).
+    ConflictingMileage(Ref, f64, f64),
+    /// Pinned mileage is on a location that is not in the track network.
+    MileagePlacement(Ref),
+    /// Simulation of a dispatch failed (dispatch name, error).
+    Simulation(String, String),
+    /// Planning failed (plan name, error).
+    Planning(String, String),
+}
+
+impl Issue {
+    pub fn location(&self) -> Option<Ref> {
+        match self {
+            Issue::UnrecognizedNode(pt) |
+            Issue::ErrorNode(pt) |
+            Issue::NodeDataMismatch(pt,_) |
+            Issue::UnconnectedTrack(pt) => Some(Ref::Node(*pt)),
+            Issue::OverlappingSegments((a,b),_) => Some(Ref::LineSeg(*a,*b)),
+            Issue::ObjectOutsideTrack(pta) |
+            Issue::ObjectPlacement(pta) => Some(Ref::Object(*pta)),
+            Issue::ExitedUnenteredSection(entry,section) => section.or(*entry),
+            Issue::RouteConversion(from,to) |
+            Issue::RouteTooShort(from,to) => from.or(*to),
+            Issue::ConflictingTrackLength((a,b),_) => Some(Ref::LineSeg(*a,*b)),
+            Issue::ConflictingMileage(r,_,_) |
+            Issue::MileagePlacement(r) => Some(*r),
+            Issue::DetectionSections(_) |
+            Issue::RouteFinder(_) |
+            Issue::NoBoundaries |
+            Issue::Simulation(_,_) |
+            Issue::Planning(_,_) => None,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Issue::UnrecognizedNode(pt) => format!("Unrecognized node shape at ({},{}).", pt.x, pt.y),
+            Issue::ErrorNode(pt) => format!("Node at ({},{}) is marked as an error.", pt.x, pt.y),
+            Issue::NodeDataMismatch(pt,nd) => format!("Node type {:?} does not fit node at ({},{}), ignored.",
+                                                      nd, pt.x, pt.y),
+            Issue::OverlappingSegments((a,b),(c,d)) =>
+                format!("Track ({},{})-({},{}) crosses track ({},{})-({},{}) without a node.",
+                        a.x, a.y, b.x, b.y, c.x, c.y, d.x, d.y),
+            Issue::UnconnectedTrack(pt) => format!("Track end at ({},{}) is not connected.", pt.x, pt.y),
+            Issue::ObjectOutsideTrack(pta) => {
+                let p = unround_coord(*pta);
+                format!("Object at ({:.1},{:.1}) is outside the track network.", p.x, p.y)
+            },
+            Issue::ObjectPlacement(pta) => {
+                let p = unround_coord(*pta);
+                format!("Object at ({:.1},{:.1}) could not be placed on the track.", p.x, p.y)
+            },
+            Issue::DetectionSections(s) => format!("Train detection sections: {}", s),
+            Issue::RouteFinder(s) => format!("Route finder: {}", s),
+            Issue::NoBoundaries => format!("No model boundaries, so no routes can be found."),
+            Issue::RouteConversion(from,to) => format!("Route from {} to {} could not be created.",
+                                                       opt_ref_name(from), opt_ref_name(to)),
+            Issue::ExitedUnenteredSection(entry,section) =>
+                format!("Route from {} exits the detection section at {} without entering it.",
+                        opt_ref_name(entry), opt_ref_name(section)),
+            Issue::RouteTooShort(from,to) => format!("Route from {} to {} is too short.",
+                                                     opt_ref_name(from), opt_ref_name(to)),
+            Issue::ConflictingTrackLength((a,b),(c,d)) =>
+                format!("Pinned lengths on {} and {} differ, but they are on the same track.",
+                        ref_name(&Ref::LineSeg(*a,*b)), ref_name(&Ref::LineSeg(*c,*d))),
+            Issue::ConflictingMileage(r,pinned,computed) =>
+                format!("Mileage {:.1} pinned on {} conflicts with other pins and lengths (computed {:.1}).",
+                        pinned, ref_name(r), computed),
+            Issue::MileagePlacement(r) => format!("Mileage pinned on {} could not be placed on the track.",
+                                                  ref_name(r)),
+            Issue::Simulation(name,e) => format!("Simulation of dispatch {:?} failed: {}", name, e),
+            Issue::Planning(name,e) => format!("Planning {:?} failed: {}", name, e),
+        }
+    }
+}
+
+/// Short human-readable description of a model reference.
+pub fn ref_name(r :&Ref) -> String {
+    match r {
+        Ref::Node(pt) => format!("node ({},{})", pt.x, pt.y),
+        Ref::LineSeg(a,b) => format!("track ({},{})-({},{})", a.x, a.y, b.x, b.y),
+        Ref::Object(pta) => {
+            let p = unround_coord(*pta);
+            format!("object ({:.1},{:.1})", p.x, p.y)
+        },
+    }
+}
+
+fn opt_ref_name(r :&Option<Ref>) -> String {
+    r.as_ref().map(ref_name).unwrap_or_else(|| format!("unknown location"))
+}
+
+/// Canvas coordinates of a model reference, used for locating issues.
+pub fn ref_location(r :&Ref) -> PtC {
+    match r {
+        Ref::Node(pt) => glm::vec2(pt.x as f32, pt.y as f32),
+        Ref::LineSeg(a,b) => glm::vec2(0.5*(a.x + b.x) as f32, 0.5*(a.y + b.y) as f32),
+        Ref::Object(pta) => unround_coord(*pta),
+    }
+}
@@ -13,7 +13,11 @@ fn take_boundary(node_ids :&BiMap<NodeId, Pt>, boundaries :&mut HashSet<NodeId>)
     } else { None }
 }
 
-pub fn auto(node_ids :&BiMap<NodeId,Pt>, inf :&StaticInfrastructure) -> HashMap<NodeId, f64> {
+/// Mileage of each node, from the edge lengths. In each connected part of
+/// the infrastructure without pinned mileages, the mileage is zero at a
+/// boundary. Otherwise, the pins are fitted in the least squares sense 
+/// together with the edge lengths.
+pub fn auto(node_ids :&BiMap<NodeId,Pt>, inf :&StaticInfrastructure, pins :&HashMap<NodeId,f64>) -> HashMap<NodeId, f64> {
     let mut boundaries : HashSet<NodeId> = inf.nodes.iter().enumerate().filter_map(|(i,n)| {
         if matches!(n.edges, Edges::ModelBoundary) { Some(i) } else { None } }).collect();
     // TODO select leftmost boundaries first
@@ -63,14 +67,21 @@ pub fn auto(node_ids :&BiMap<NodeId,Pt>, inf :&StaticInfrastructure) -> HashMap<
 
     let mut varmap : HashMap<NodeId, usize> = HashMap::new();
     let mut varidx = -1isize;
-    let mut fixed :HashMap<NodeId, Option<usize>> = fixed.into_iter().map(|n| (n,None)).collect();
+    // Components without a pinned mileage are anchored at zero at their 
+    // first boundary.
+    let pinned :HashSet<NodeId> = pins.keys().map(|n| uf.find_mut(*n)).collect();
+    let mut fixed :HashMap<NodeId, (Option<usize>, f64)> = fixed.into_iter()
+        .filter(|n| !pinned.contains(&uf.find_mut(*n)))
+        .map(|n| (n,(None,0.0)))
+        .chain(pins.iter().map(|(n,km)| (*n,(None,*km))))
+        .collect();
     for (n,km) in km0.iter() {
         let v = varmap.entry(uf.find_mut(*n)).or_insert_with(|| { varidx += 1; varidx as usize });
-        fixed.entry(*n).and_modify(|nvar| { *nvar = Some(*v); });
+        fixed.entry(*n).and_modify(|(nvar,_)| { *nvar = Some(*v); });
     }
 
     let mut rhs : Vec<f64> = edges.iter().map(|(_,_,d)| *d).collect();
-    for (_,km) in fixed.iter() { rhs.push(0.0); } // TODO fixed positions can be != 0.0 as well
+    for (_,(_,km)) in fixed.iter() { rhs.push(*km); }
 
 
     //println!("varmap {:?}", varmap);
@@ -105,7 +116,7 @@ pub fn auto(node_ids :&BiMap<NodeId,Pt>, inf :&StaticInfrastructure) -> HashMap<
                                let (a,b) = (varmap[&uf.find_mut(*a)], varmap[&uf.find_mut(*b)]);
                                y[e] += x[b] - x[a];
                            }
-                           for (i,(_,v)) in fixed.iter().enumerate() { if let Some(var) = v { 
+                           for (i,(_,(v,_))) in fixed.iter().enumerate() { if let Some(var) = v { 
                                //println!("edges.len()  = {}", edges.len());
                                //println!("i = {}", i);
                                //println!("var = {}", *var);
@@ -126,7 +137,7 @@ pub fn auto(node_ids :&BiMap<NodeId,Pt>, inf :&StaticInfrastructure) -> HashMap<
                                 x[a] += -1. * y[e];
                                 x[b] +=  1. * y[e];
                             }
-                           for (i,(_,v)) in fixed.iter().enumerate() { if let Some(var) = v { 
+                           for (i,(_,(v,_))) in fixed.iter().enumerate() { if let Some(var) = v { 
                                 x[*var] += y[edges.len() + i] ;
                            } }
                             //println!("XAddATy end");
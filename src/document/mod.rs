@@ -13,6 +13,11 @@ pub mod history;
 pub mod dispatch;
 pub mod mileage;
 pub mod plan;
+pub mod issues;
+pub mod routetable;
+pub mod plantemplates;
+pub mod capacity;
+pub mod dynamics;
 
 // graphical view representation
 pub mod infview;
@@ -86,6 +91,13 @@ pub struct ManualDispatchView {
     pub action :ManualDispatchViewAction,
     pub viewport :Option<DiagramViewport>,
     pub selected_command :Option<usize>,
+    /// Show the compressed timetable and capacity consumption.
+    pub show_capacity :bool,
+    /// Mileage interval of the line section for capacity consumption,
+    /// or the whole diagram if `None`.
+    pub line_section :Option<(f64,f64)>,
+    /// Show blocking time stairways instead of section occupations.
+    pub show_blocking_times :bool,
 }
 
 impl ManualDispatchView {
@@ -97,6 +109,9 @@ impl ManualDispatchView {
             viewport: None,
             action: ManualDispatchViewAction::None,
             selected_command: None,
+            show_capacity: false,
+            line_section: None,
+            show_blocking_times: true,
         }
     }
 }
@@ -121,6 +136,9 @@ pub struct AutoDispatchView {
     pub plan_idx :usize,
     pub action :PlanViewAction,
     pub dispatch :Option<ManualDispatchView>,
+    /// Identity of the selected dispatch (see `plan::dispatch_key`), used to
+    /// find it again when the plan's dispatches are recomputed.
+    pub dispatch_key :Option<u64>,
 }
 
 
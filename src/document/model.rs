@@ -51,6 +51,9 @@ pub struct Vehicle {
     pub max_acc :f32,
     pub max_brk :f32,
     pub max_vel :f32,
+    /// Physical model used to derive the acceleration and braking on gradients.
+    #[serde(default)]
+    pub dynamics :Option<VehicleDynamics>,
 }
 
 impl Default for Vehicle {
@@ -60,6 +63,38 @@ impl Default for Vehicle {
         max_acc: 0.9,
         max_brk: 0.85,
         max_vel: 50.0,
+        dynamics: None,
+    } }
+}
+
+/// Mass and running resistance of a vehicle. Forces are in kN and the
+/// mass in tonnes, so that force over mass is an acceleration in m/s^2.
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(Serialize,Deserialize)]
+pub struct VehicleDynamics {
+    /// Mass (t).
+    pub mass :f32,
+    /// Rotating mass factor, multiplied with the mass when accelerating.
+    pub rotating_mass :f32,
+    /// Davis resistance coefficients: R(v) = a + b v + c v^2 (kN, v in m/s).
+    pub davis :(f32,f32,f32),
+    /// Tractive effort curve as (speed (m/s), force (kN)) points, 
+    /// interpolated linearly and held constant outside the points.
+    pub tractive_effort :Vec<(f32,f32)>,
+    /// Braking force curve as (speed (m/s), force (kN)) points. When empty,
+    /// the vehicle brakes with its constant `max_brk` deceleration.
+    #[serde(default)]
+    pub brake_effort :Vec<(f32,f32)>,
+}
+
+impl Default for VehicleDynamics {
+    fn default() -> VehicleDynamics { VehicleDynamics {
+        mass: 400.0,
+        rotating_mass: 1.06,
+        davis: (4.0, 0.04, 0.008),
+        tractive_effort: vec![(0.0, 300.0), (20.0, 300.0), (50.0, 120.0)],
+        brake_effort: Vec::new(),
     } }
 }
 
@@ -128,6 +163,7 @@ pub struct RouteSpec {
 
 #[derive(Copy, Clone)]
 #[derive(Debug)]
+#[derive(Hash, PartialEq, Eq)]
 #[derive(Serialize,Deserialize)]
 pub enum Command {
     Train(usize, RouteSpec),
@@ -142,6 +178,37 @@ pub struct Dispatch {
     pub name :String,
     generation :usize,
     pub commands :Vec<(usize,(f64,Command))>,
+    /// Train properties by the id of their train command.
+    #[serde(default)]
+    pub trains :im::HashMap<usize, TrainEntry>,
+}
+
+/// Optional properties of a train entering through a train command.
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrainEntry {
+    /// Train name or number, instead of a generated name.
+    pub name :Option<String>,
+    /// Speed when entering through a model boundary.
+    #[serde(default)]
+    pub entry_speed :EntrySpeed,
+}
+
+/// Speed of a train entering through a train command. Trains whose
+/// route starts inside the model always start from standstill.
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EntrySpeed {
+    Standstill,
+    /// The vehicle's maximum velocity, or the first speed limit on the
+    /// train's entry route if it is lower.
+    LineSpeed,
+    /// Speed (km/h).
+    Speed(f64),
+}
+
+impl Default for EntrySpeed {
+    fn default() -> Self { EntrySpeed::LineSpeed }
 }
 
 impl Dispatch {
@@ -150,6 +217,7 @@ impl Dispatch {
             name: name,
             generation :0,
             commands :Vec::new(),
+            trains :Default::default(),
         }
     }
 
@@ -159,6 +227,7 @@ impl Dispatch {
             name: name, 
             generation: l,
             commands: commands,
+            trains: Default::default(),
         }
     }
 
@@ -179,6 +248,81 @@ pub struct PlanSpec {
     pub name :String,
     pub trains: ImShortGenList<(Option<ListId>, ImShortGenList<Visit>)>,
     pub order :Vec<(VisitRef,VisitRef,Option<f64>)>,
+    #[serde(default)]
+    pub config :PlanConfig,
+    /// Minimum times (s) from the first to the second visit. 
+    #[serde(default)]
+    pub min_times :Vec<(VisitRef,VisitRef,f64)>,
+}
+
+/// Search limits for the planner.
+#[derive(Clone, Debug)]
+#[derive(Serialize,Deserialize)]
+#[serde(default)]
+pub struct PlanConfig {
+    /// Number of planner states to search before the first train visit.
+    pub n_before :usize,
+    /// Number of planner states to search after the last train visit.
+    pub n_after :usize,
+    /// Search only plans with exactly this number of states.
+    pub exact_n :Option<usize>,
+    pub optimize_signals :bool,
+    /// Stop searching after this many dispatches are found.
+    pub max_dispatches :Option<usize>,
+    /// Stop searching after this time (s), including the search for
+    /// conflicting ordering constraints. The time is checked each time
+    /// the planner finds a candidate.
+    pub timeout :Option<f64>,
+    /// Order of the resulting dispatches, best first.
+    pub objective :PlanObjective,
+    /// Repeat the plan with this cycle time (s). Dispatches that cannot be
+    /// sustained when repeated are rejected.
+    pub cycle_time :Option<f64>,
+}
+
+/// Measure for ranking the dispatches found for a plan (lower is better).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Serialize,Deserialize)]
+pub enum PlanObjective {
+    /// Time until the last train has left the model.
+    CompletionTime,
+    /// Sum of the time each train spends in the model.
+    RunningTime,
+    /// Number of route commands.
+    RouteCommands,
+    /// Sum of the time each train spends standing still.
+    WaitingTime,
+}
+
+impl PlanObjective {
+    pub fn all() -> &'static [PlanObjective] {
+        &[PlanObjective::CompletionTime, PlanObjective::RunningTime,
+          PlanObjective::RouteCommands, PlanObjective::WaitingTime]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlanObjective::CompletionTime => "Completion time",
+            PlanObjective::RunningTime => "Running time",
+            PlanObjective::RouteCommands => "Route commands",
+            PlanObjective::WaitingTime => "Waiting time",
+        }
+    }
+}
+
+impl Default for PlanConfig {
+    fn default() -> Self {
+        PlanConfig {
+            n_before: 3,
+            n_after: 3,
+            exact_n: None,
+            optimize_signals: false,
+            max_dispatches: None,
+            timeout: None,
+            objective: PlanObjective::CompletionTime,
+            cycle_time: None,
+        }
+    }
 }
 
 impl PlanSpec {
@@ -187,6 +331,8 @@ impl PlanSpec {
             name: name,
             trains: Default::default(),
             order: Default::default(),
+            config: Default::default(),
+            min_times: Default::default(),
         }
     }
 }
@@ -289,6 +435,88 @@ pub struct Model {
     pub vehicles :ImShortGenList<Vehicle>, 
     pub dispatches :ImShortGenList<Dispatch>,
     pub plans :ImShortGenList<PlanSpec>,
+    #[serde(default)]
+    pub settings :Settings,
+    /// Surveyed track lengths (m). A track is the run of line segments
+    /// between two nodes, and can be pinned through any of its segments.
+    #[serde(default)]
+    pub track_lengths :im::HashMap<(Pt,Pt), f64>,
+    /// Absolute mileages (m) pinned on nodes and objects.
+    #[serde(default)]
+    pub mileages :im::HashMap<Ref, f64>,
+    /// Speed restrictions (km/h) on the diverging branch of switches,
+    /// and on all paths through crossings.
+    #[serde(default)]
+    pub node_speeds :im::HashMap<Pt, u32>,
+    /// Gradients (per mille) on line segments, rising from the first to the second point.
+    #[serde(default)]
+    pub gradients :im::HashMap<(Pt,Pt), f64>,
+}
+
+/// Per-document settings for the analysis.
+#[derive(Clone, Default)]
+#[derive(Debug)]
+#[derive(Serialize,Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub interlocking :InterlockingSettings,
+    pub timing :TimingSettings,
+}
+
+/// Fixed times added to the simulated section occupations when showing
+/// blocking time stairways.
+#[derive(Clone, PartialEq)]
+#[derive(Debug)]
+#[derive(Serialize,Deserialize)]
+#[serde(default)]
+pub struct TimingSettings {
+    /// Time to set up a route before it is reserved (s).
+    pub route_setting :f64,
+    /// Sight and reaction time before the train reaches the approach (s).
+    pub sight :f64,
+    /// Time to release a section after it has been vacated (s).
+    pub release :f64,
+}
+
+impl Default for TimingSettings {
+    fn default() -> Self {
+        TimingSettings {
+            route_setting: 6.0,
+            sight: 12.0,
+            release: 3.0,
+        }
+    }
+}
+
+/// Parameters given to the route finder.
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(Serialize,Deserialize)]
+#[serde(default)]
+pub struct InterlockingSettings {
+    /// Detection sections overlapping a route by less than this length (m)
+    /// are not part of the route.
+    pub section_tolerance :f64,
+    /// Minimum route length (m).
+    pub route_minimum_length :f64,
+    /// Overlap length past the exit signal (m). Zero gives no overlaps.
+    pub overlap_length :f64,
+    /// Time before the overlap is released when the train has stopped (s).
+    pub overlap_timeout :Option<f64>,
+    /// Lock adjacent switches and signals to protect the route's flanks.
+    pub flank_protection :bool,
+}
+
+impl Default for InterlockingSettings {
+    fn default() -> Self {
+        InterlockingSettings {
+            section_tolerance: 15.0,
+            route_minimum_length: 15.0,
+            overlap_length: 0.0,
+            overlap_timeout: None,
+            flank_protection: false,
+        }
+    }
 }
 
 
@@ -383,10 +611,11 @@ impl Model {
 
     pub fn delete(&mut self, x :Ref) {
         match x {
-            Ref::LineSeg(a,b) => { self.linesegs.remove(&(a,b)); },
-            Ref::Node(a) => { self.node_data.remove(&a); },
+            Ref::LineSeg(a,b) => { self.linesegs.remove(&(a,b)); self.track_lengths.remove(&(a,b)); self.gradients.remove(&(a,b)); },
+            Ref::Node(a) => { self.node_data.remove(&a); self.node_speeds.remove(&a); },
             Ref::Object(p) => { self.objects.remove(&p); },
         }
+        self.mileages.remove(&x);
     }
 
 
@@ -405,6 +634,24 @@ pub enum EditClass {
 
     DispatchName(usize),
     PlanName(usize),
+    VisitDwell(usize,usize,usize),
+    PlanConfig(usize),
+
+    SectionTolerance,
+    RouteMinimumLength,
+    OverlapLength,
+    OverlapTimeout,
+    FlankProtection,
+    RouteSettingTime,
+    SightTime,
+    ReleaseTime,
+    TrackLength(Pt,Pt),
+    Mileage(Ref),
+    NodeSpeed(Pt),
+    ObjectSpeed(PtA),
+    Gradient(Pt,Pt),
+    VehicleDynamics(usize),
+    TrainEntry(usize,usize),
 }
 
 
@@ -21,7 +21,12 @@ pub struct Object {
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[derive(Serialize,Deserialize)]
-pub enum Function { MainSignal { has_distant :bool }, Detector }
+pub enum Function {
+    MainSignal { has_distant :bool },
+    Detector,
+    /// Speed limit sign (km/h), valid in the direction of the object's tangent.
+    SpeedLimit { speed :u32 },
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ObjectState { SignalStop, SignalProceed, DistantStop, DistantProceed }
@@ -35,7 +40,7 @@ impl Object {
             let normal : PtC   = glm::vec2(-tangent.y,tangent.x);
             self.tangent = glm::vec2(tangent.x.round() as _, tangent.y.round() as _);
 
-            if self.functions.iter().find(|c| matches!(c, Function::MainSignal { .. })).is_some() {
+            if self.functions.iter().find(|c| matches!(c, Function::MainSignal { .. } | Function::SpeedLimit { .. })).is_some() {
                     let factor = if glm::angle(&(pt_on_line - pt), &normal) > glm::half_pi() {
                         1.0 } else { -1.0 };
                     let offset = 0.25*normal*factor;
@@ -103,6 +108,13 @@ impl Object {
                         // main signal
                         ImDrawList_AddCircle(draw_list, p + stem*tangent + tangent, scale, c, 8, 2.0);
                     },
+                    Function::SpeedLimit { speed } => {
+                        ImDrawList_AddLine(draw_list, p + normal, p - normal, c, 2.0);
+                        ImDrawList_AddLine(draw_list, p, p + tangent, c, 2.0);
+                        let text = format!("{}", speed);
+                        ImDrawList_AddText(draw_list, p + 1.5*tangent - ImVec2 { x: 2.0*scale, y: scale },
+                                           c, text.as_ptr() as _, text.as_ptr().offset(text.len() as isize) as _);
+                    },
                 }
 
             }
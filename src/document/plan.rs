@@ -1,10 +1,16 @@
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use crate::document::interlocking::*;
 use rolling::input::staticinfrastructure as rolling_inf;
 use crate::document::model::*;
 use crate::document::history;
 use crate::document::dgraph::DGraph;
+use crate::util;
+use nalgebra_glm as glm;
 use rolling::output::history::*;
+use rolling::railway::dynamics::DistanceVelocity;
+use matches::matches;
 
 #[derive(Debug)]
 pub enum ConvertPlanErr {
@@ -16,7 +22,59 @@ pub enum TestPlanErr {
     MissingVisits,
     MissingTrain,
     VisitOrderError,
-    TimingError,
+    /// Ordered visits happened in the wrong order, or too far apart.
+    TimingError(TimingErr),
+    /// The train did not stop long enough at a visit with a dwell time.
+    DwellTimeError(DwellError),
+    /// The dispatch cannot be repeated with the plan's cycle time.
+    NotSustainable(f64),
+    /// Simulating the dispatch failed.
+    Simulation(String),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TimingErr {
+    pub a :VisitRef,
+    pub b :VisitRef,
+    pub t_a :f64,
+    pub t_b :f64,
+    pub max_time :Option<f64>,
+    pub min_time :Option<f64>,
+}
+
+impl TestPlanErr {
+    pub fn message(&self) -> String {
+        match self {
+            TestPlanErr::MissingVisits => format!("a train did not reach all its visits"),
+            TestPlanErr::MissingTrain => format!("a train was not dispatched"),
+            TestPlanErr::VisitOrderError => format!("an ordered visit was not reached"),
+            TestPlanErr::TimingError(TimingErr { a, b, t_a, t_b, max_time, min_time }) => {
+                if t_a > t_b {
+                    format!("train {} visit {} came before train {} visit {}", b.0, b.1, a.0, a.1)
+                } else if let Some(min_time) = min_time {
+                    format!("train {} visit {} came {:.0} s after train {} visit {}, less than {:.0} s",
+                            b.0, b.1, t_b - t_a, a.0, a.1, min_time)
+                } else {
+                    format!("train {} visit {} came {:.0} s after train {} visit {}, more than {:.0} s",
+                            b.0, b.1, t_b - t_a, a.0, a.1, max_time.unwrap_or(0.0))
+                }
+            },
+            TestPlanErr::DwellTimeError(DwellError { train, visit, missing, .. }) =>
+                format!("train {} stopped {:.0} s too short at visit {}", train, missing, visit),
+            TestPlanErr::NotSustainable(cycle) => format!("cannot be repeated every {:.0} s", cycle),
+            TestPlanErr::Simulation(e) => format!("simulation failed: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DwellError {
+    pub train :usize,
+    pub visit :usize,
+    /// Time when the train reached the visit location.
+    pub time :f64,
+    /// Remaining dwell time that was not spent standing still.
+    pub missing :f64,
 }
 
 pub fn eval_plan(dgraph :&DGraph, plan_spec :&PlanSpec, history :&History) -> Result<(), TestPlanErr> {
@@ -30,21 +88,41 @@ pub fn eval_plan(dgraph :&DGraph, plan_spec :&PlanSpec, history :&History) -> Re
         let mut t = 0.0;
         let mut current_visit = 0;
         let (train_name, train_params, train_log) = history.trains.get(train_idx).ok_or(TestPlanErr::MissingTrain)?;
+        let visit_locations = visits.data().iter()
+            .map(|(_,v)| v.locs.iter().map(|l| location_match(dgraph, l)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let mut dwell = DwellCheck::default();
+        let dwell_err = |(visit, time, missing) :(usize,f64,f64)| TestPlanErr::DwellTimeError(DwellError {
+            train: *train_id, visit, time, missing });
+
         for ev in train_log.iter() {
 
             if let TrainLogEvent::Wait(dt) = ev { 
                 t += dt;
+                dwell.stand(*dt);
             }
-            if let TrainLogEvent::Move(dt,_,_) = ev { 
+            if let TrainLogEvent::Move(dt,_,DistanceVelocity { dx, .. }) = ev { 
                 t += dt;
+                if *dx > 0.0 { dwell.drive(*dx); } else { dwell.stand(*dt); }
+            }
+
+            if let TrainLogEvent::Node(_) | TrainLogEvent::Edge(_,_) = ev {
+                let left = dwell.visit().map(|v| !event_matches_spec(&visit_locations[v], ev)).unwrap_or(false);
+                if left { if let Some(e) = dwell.leave() { return Err(dwell_err(e)); } }
             }
 
             if !(current_visit < visits.data().len()) { 
-                break;
+                if dwell.visit().is_none() { break; } else { continue; }
             }
 
-            if event_matches_spec(dgraph, &visits.data()[current_visit].1, ev) {
-                visit_times.insert((*train_id, visits.data()[current_visit].0), t);
+            if event_matches_spec(&visit_locations[current_visit], ev) {
+                if let Some(e) = dwell.leave() { return Err(dwell_err(e)); }
+                let (visit_id, visit) = &visits.data()[current_visit];
+                visit_times.insert((*train_id, *visit_id), t);
+                if let Some(d) = visit.dwell {
+                    if d > 0.0 { dwell.arrive(current_visit, t, d); }
+                }
                 current_visit += 1;
             }
         }
@@ -52,58 +130,385 @@ pub fn eval_plan(dgraph :&DGraph, plan_spec :&PlanSpec, history :&History) -> Re
             // train train_idx failed to reach its planned locations
             return Err(TestPlanErr::MissingVisits); 
         }
+        if let Some(e) = dwell.leave() { return Err(dwell_err(e)); }
     }
 
     // 2. check ordering constraints and time diff
     for (ra,rb,dt) in plan_spec.order.iter() {
         let t1 = visit_times.get(ra).ok_or(TestPlanErr::VisitOrderError)?;
         let t2 = visit_times.get(rb).ok_or(TestPlanErr::VisitOrderError)?;
+        let err = TestPlanErr::TimingError(TimingErr { a: *ra, b: *rb, t_a: *t1, t_b: *t2, 
+                                                       max_time: *dt, min_time: None });
         // Visits happen in order
         if !(t1 <= t2) {
-            return Err(TestPlanErr::TimingError);
+            return Err(err);
         }
         // Visits happen within time limit
         if let Some(dt) = dt {
-            if !(t1 + dt >= *t2) {
-                return Err(TestPlanErr::TimingError);
+            if !(t1 + dt + TIMING_TOLERANCE >= *t2) {
+                return Err(err);
             }
         }
     }
 
+    // 3. check minimum times between visits
+    for (ra,rb,dt) in plan_spec.min_times.iter() {
+        let t1 = visit_times.get(ra).ok_or(TestPlanErr::VisitOrderError)?;
+        let t2 = visit_times.get(rb).ok_or(TestPlanErr::VisitOrderError)?;
+        if !(t1 + dt - TIMING_TOLERANCE <= *t2) {
+            return Err(TestPlanErr::TimingError(TimingErr { a: *ra, b: *rb, t_a: *t1, t_b: *t2, 
+                                                            max_time: None, min_time: Some(*dt) }));
+        }
+    }
+
     Ok(())
 }
 
+const DWELL_TOLERANCE :f64 = 0.1;
+const TIMING_TOLERANCE :f64 = 0.1;
+/// A train which has moved less than this distance (m) since it stopped
+/// is still standing at the same place.
+const STANDSTILL_DISTANCE :f64 = 1.0;
+
+/// Standstill time at a visit location with a dwell time. The dwell counts
+/// the train's standstill right before reaching the location (e.g. in front
+/// of a signal at the location), and while the train is at the location
+/// (e.g. stopping at the end of a track segment). Stops before the train
+/// gets there, or after it has left, do not count.
+#[derive(Debug, Default)]
+struct DwellCheck {
+    /// Duration of the current, or last, standstill.
+    standstill :f64,
+    /// Distance moved since the last standstill.
+    moved :f64,
+    /// The visit being dwelled at: (visit idx, time, dwell, standstill at the location).
+    at_visit :Option<(usize,f64,f64,f64)>,
+}
+
+impl DwellCheck {
+    fn stand(&mut self, dt :f64) {
+        if self.moved > STANDSTILL_DISTANCE { self.standstill = 0.0; }
+        self.moved = 0.0;
+        self.standstill += dt;
+        if let Some((_,_,_,stopped)) = &mut self.at_visit { *stopped += dt; }
+    }
+
+    fn drive(&mut self, dx :f64) {
+        self.moved += dx;
+    }
+
+    fn visit(&self) -> Option<usize> {
+        self.at_visit.map(|(visit,_,_,_)| visit)
+    }
+
+    fn arrive(&mut self, visit :usize, time :f64, dwell :f64) {
+        let stopped = if self.moved <= STANDSTILL_DISTANCE { self.standstill } else { 0.0 };
+        self.at_visit = Some((visit, time, dwell, stopped));
+    }
+
+    /// The train left the visit location. Returns the visit, its time and the 
+    /// missing dwell time if the train did not stand still long enough.
+    fn leave(&mut self) -> Option<(usize,f64,f64)> {
+        let (visit, time, dwell, stopped) = self.at_visit.take()?;
+        if stopped < dwell - DWELL_TOLERANCE { Some((visit, time, dwell - stopped)) } else { None }
+    }
+}
+const REFINE_MAX_ITERATIONS :usize = 10;
+
 pub fn test_plan(dgraph :&DGraph,
                  il :&Interlocking, 
                  vehicles :&[(usize,Vehicle)],
                  plan_spec :&PlanSpec,
                  candidate :&planner::input::RoutePlan) 
     -> Result<Result<(Commands, History),TestPlanErr>,String> {
-    let commands = convert_dispatch_commands(candidate, il, plan_spec)?;
+    let (mut commands, mut command_trains) = convert_dispatch_commands(candidate, il, plan_spec)?;
+
+    // The planner has no notion of time, so dwell times and maximum times
+    // between visits are handled by shifting commands in time and simulating
+    // again, until the plan is satisfied or cannot be improved.
+    for _ in 0..REFINE_MAX_ITERATIONS {
+        // simulate the dispatch
+        let (history,route_refs) =
+             history::get_history(vehicles, dgraph, il, &commands, &Default::default())?;
+
+        // then check that the plan is satisfied
+        let refined = match eval_plan(dgraph, plan_spec, &history) {
+            Ok(()) => return Ok(Ok((commands,history))),
+            Err(TestPlanErr::DwellTimeError(err)) => 
+                delay_for_dwell(dgraph, il, plan_spec, &mut commands, &mut command_trains, &err),
+            Err(TestPlanErr::TimingError(err)) if err.min_time.is_some() =>
+                delay_for_min_time(&mut commands, &mut command_trains, &err),
+            Err(TestPlanErr::TimingError(err)) =>
+                delay_for_max_time(&mut commands, &mut command_trains, &err),
+            Err(e) => return Ok(Err(e)),
+        };
+        if !refined { 
+            return Ok(eval_plan(dgraph, plan_spec, &history).map(|()| (commands,history)));
+        }
+    }
+
+    let (history,_) = history::get_history(vehicles, dgraph, il, &commands, &Default::default())?;
+    Ok(eval_plan(dgraph, plan_spec, &history).map(|()| (commands,history)))
+}
 
-    // simulate the dispatch
-    let (history,route_refs) =
-         history::get_history(vehicles, &dgraph.rolling_inf, il, &commands)?;
+/// Delay the train of the first visit in an ordering constraint, so that
+/// the second visit happens within the maximum time after it. Returns
+/// false if the constraint can not be fixed by delaying.
+fn delay_for_max_time(commands :&mut Commands, command_trains :&mut Vec<usize>, err :&TimingErr) -> bool {
+    let (train_a, train_b) = ((err.a).0, (err.b).0);
+    let max_time = match err.max_time {
+        Some(dt) if train_a != train_b && err.t_a <= err.t_b => dt,
+        _ => return false,
+    };
+    let shift = err.t_b - err.t_a - max_time;
+    if !(shift > 0.0) { return false; }
 
-    // then check that the plan is satisfied
-    if let Err(e) = eval_plan(dgraph, plan_spec, &history) { return Ok(Err(e)); }
-    return Ok(Ok((commands,history)));
+    for (i,(_,(t,_))) in commands.iter_mut().enumerate() {
+        if command_trains[i] == train_a { *t += shift; }
+    }
+    sort_commands(commands, command_trains);
+    true
 }
 
-fn event_matches_spec(dgraph :&DGraph, visit :&Visit, event :&TrainLogEvent) -> bool {
-    if let TrainLogEvent::Node(n) = event {
-        for l in visit.locs.iter() {
-            match l {
-                Ok(Ref::Node(pt)) => {
-                    // have to check both boundaries and switch nodes in the dgraph
-                    if dgraph.node_ids.get_by_left(n) == Some(pt) { return true; }
-                    if dgraph.switch_ids.get_by_left(n) == Some(pt) { return true; }
-                },
-                _ => unimplemented!(), // TODO
-            };
+/// Delay the train of the second visit in a minimum time constraint, so 
+/// that it happens at least the minimum time after the first visit.
+/// Returns false if the constraint can not be fixed by delaying.
+fn delay_for_min_time(commands :&mut Commands, command_trains :&mut Vec<usize>, err :&TimingErr) -> bool {
+    let (train_a, train_b) = ((err.a).0, (err.b).0);
+    let min_time = match err.min_time {
+        Some(dt) if train_a != train_b => dt,
+        _ => return false,
+    };
+    let shift = err.t_a + min_time - err.t_b;
+    if !(shift > 0.0) { return false; }
+
+    for (i,(_,(t,_))) in commands.iter_mut().enumerate() {
+        if command_trains[i] == train_b { *t += shift; }
+    }
+    sort_commands(commands, command_trains);
+    true
+}
+
+/// Delay the command that sets the route out of the visit location which
+/// was not given enough dwell time. Commands of the same train that follow
+/// it are delayed as well, so they stay in order. Returns false if there is
+/// no such route command.
+fn delay_for_dwell(dgraph :&DGraph, il :&Interlocking, plan_spec :&PlanSpec,
+                   commands :&mut Commands, command_trains :&mut Vec<usize>, err :&DwellError) -> bool {
+    let visit = match plan_spec.trains.get(err.train).and_then(|(_,v)| v.data().get(err.visit)) {
+        Some((_,v)) => v,
+        None => return false,
+    };
+    let locs = visit.locs.iter().map(|l| location_match(dgraph, l)).collect::<Vec<_>>();
+
+    // Find the first route of the train that passes the location, 
+    // and delay the next command of the same train.
+    let train_cmds = commands.iter().enumerate()
+        .filter(|(i,_)| command_trains[*i] == err.train)
+        .map(|(i,(_,(_,cmd)))| (i, cmd)).collect::<Vec<_>>();
+    let passing = train_cmds.iter().position(|(_,cmd)| {
+        let spec = match cmd { Command::Train(_,spec) | Command::Route(spec) => spec };
+        il.find_route(spec).map(|r| locs.iter().any(|l| l.matches_path(&il.routes[*r].path)))
+            .unwrap_or(false)
+    });
+    let delayed = match passing.and_then(|p| train_cmds.get(p+1)) {
+        Some((i,_)) => *i,
+        None => return false,
+    };
+
+    let old_time = (commands[delayed].1).0;
+    let new_time = if old_time < err.time { err.time + err.missing } else { old_time + err.missing };
+    for i in delayed..commands.len() {
+        if command_trains[i] == err.train {
+            let t = &mut (commands[i].1).0;
+            *t = t.max(new_time);
         }
     }
-    false
+
+    sort_commands(commands, command_trains);
+    true
+}
+
+/// Commands are executed in sequence, so keep them sorted by time.
+fn sort_commands(commands :&mut Commands, command_trains :&mut Vec<usize>) {
+    let mut sorted = commands.drain(..).zip(command_trains.drain(..)).collect::<Vec<_>>();
+    sorted.sort_by(|((_,(a,_)),_),((_,(b,_)),_)| a.partial_cmp(b).unwrap());
+    for (cmd,train) in sorted {
+        commands.push(cmd);
+        command_trains.push(train);
+    }
+}
+
+/// The parts of the dgraph where a train is visiting a plan location.
+/// Nodes match when the train passes the node, edges match when the
+/// train enters the edge (edges are stored in both directions).
+#[derive(Debug, Default)]
+pub struct LocationMatch {
+    pub nodes :HashSet<rolling_inf::NodeId>,
+    pub edges :HashSet<(rolling_inf::NodeId, rolling_inf::NodeId)>,
+}
+
+impl LocationMatch {
+    fn add_node(&mut self, dgraph :&DGraph, n :rolling_inf::NodeId) {
+        self.nodes.insert(n);
+        self.nodes.insert(dgraph.rolling_inf.nodes[n].other_node);
+    }
+
+    fn add_edge(&mut self, a :rolling_inf::NodeId, b :rolling_inf::NodeId) {
+        self.edges.insert((a,b));
+        self.edges.insert((b,a));
+    }
+
+    /// Does the route path pass through this location?
+    pub fn matches_path(&self, path :&[(rolling_inf::NodeId, rolling_inf::NodeId)]) -> bool {
+        path.iter().any(|(a,b)| self.edges.contains(&(*a,*b)) ||
+                        self.nodes.contains(a) || self.nodes.contains(b))
+    }
+}
+
+pub fn location_match(dgraph :&DGraph, loc :&PlanLoc) -> LocationMatch {
+    let mut m = LocationMatch::default();
+    match loc {
+        Ok(Ref::Node(pt)) => {
+            // have to check both boundaries and switch nodes in the dgraph
+            if let Some(n) = dgraph.node_ids.get_by_right(pt) { m.add_node(dgraph, *n); }
+            if let Some(sw) = dgraph.switch_ids.get_by_right(pt) {
+                for (n,node) in dgraph.rolling_inf.nodes.iter().enumerate() {
+                    if let rolling_inf::Edges::Switchable(x) = node.edges {
+                        if x == *sw { m.add_node(dgraph, n); }
+                    }
+                }
+            }
+        },
+        Ok(Ref::Object(pta)) => {
+            // signals (and other objects) are placed on nodes, detectors are nodes.
+            if let Some(obj) = dgraph.object_ids.get_by_right(pta) {
+                for (n,node) in dgraph.rolling_inf.nodes.iter().enumerate() {
+                    if node.objects.contains(obj) { m.add_node(dgraph, n); }
+                }
+            }
+            if let Some(n) = dgraph.detector_ids.get_by_right(pta) { m.add_node(dgraph, *n); }
+        },
+        Ok(Ref::LineSeg(a,b)) => {
+            // edges which have a piece of their line on the line segment
+            let a = glm::vec2(a.x as f32, a.y as f32);
+            let b = glm::vec2(b.x as f32, b.y as f32);
+            for ((n1,n2),line) in dgraph.edge_lines.iter() {
+                let on_lineseg = line.iter().zip(line.iter().skip(1)).any(|(p,q)| {
+                    let (d,_) = util::dist_to_line_sqr(glm::lerp(p,q,0.5), a, b);
+                    d < 1e-4 && glm::distance2(p,q) > 1e-8
+                });
+                if on_lineseg { m.add_edge(*n1, *n2); }
+            }
+        },
+        Err(pt) => {
+            // the edges closest to the point
+            let dists = dgraph.edge_lines.iter().map(|(e,line)| {
+                let d = line.iter().zip(line.iter().skip(1))
+                    .map(|(p,q)| util::dist_to_line_sqr(*pt, *p, *q).0)
+                    .fold(std::f32::INFINITY, f32::min);
+                (*e,d)
+            }).collect::<Vec<_>>();
+            let min_dist = dists.iter().map(|(_,d)| *d).fold(std::f32::INFINITY, f32::min);
+            for ((n1,n2),d) in dists {
+                if d <= min_dist + 1e-4 { m.add_edge(n1, n2); }
+            }
+        },
+    }
+    m
+}
+
+fn event_matches_spec(locs :&[LocationMatch], event :&TrainLogEvent) -> bool {
+    match event {
+        TrainLogEvent::Node(n) => locs.iter().any(|l| l.nodes.contains(n)),
+        TrainLogEvent::Edge(a,Some(b)) => locs.iter().any(|l| l.edges.contains(&(*a,*b))),
+        _ => false,
+    }
+}
+
+/// Summary of a planning run, explaining why a plan has few or no dispatches.
+#[derive(Debug, Clone, Default)]
+pub struct PlanDiagnostics {
+    /// Number of route plans found by the planner.
+    pub candidates :usize,
+    /// Number of candidates that satisfied the plan in simulation.
+    pub accepted :usize,
+    /// Reasons for rejecting candidates, with the number of candidates for each.
+    pub rejections :Vec<(String, usize)>,
+    /// When the planner finds no candidates: a minimal set of ordering
+    /// constraints (indices into `PlanSpec::order`) that cannot be
+    /// satisfied together. `Some(vec![])` means that the visits cannot be
+    /// reached even without ordering constraints.
+    pub conflicting_order :Option<Vec<usize>>,
+    /// The search was stopped by the plan's dispatch limit or timeout.
+    pub stopped :Option<String>,
+    /// Number of accepted candidates that were equal to another dispatch
+    /// up to timing, and were merged into it.
+    pub merged :usize,
+}
+
+impl PlanDiagnostics {
+    pub fn lines(&self, plan :&PlanSpec) -> Vec<String> {
+        let mut lines = vec![format!("{} planner candidates, {} accepted, {} rejected.",
+                                     self.candidates, self.accepted, self.candidates - self.accepted)];
+        if self.merged > 0 {
+            lines.push(format!("{} duplicate dispatches merged.", self.merged));
+        }
+        for (msg,n) in self.rejections.iter() {
+            lines.push(format!("{} x {}", n, msg));
+        }
+        if let Some(reason) = &self.stopped {
+            lines.push(format!("Search stopped: {}.", reason));
+        }
+        match &self.conflicting_order {
+            Some(order) if order.is_empty() => {
+                lines.push(format!("The visits cannot be reached even without ordering constraints."));
+            },
+            Some(order) => {
+                lines.push(format!("These ordering constraints cannot be satisfied together:"));
+                for i in order {
+                    if let Some(((ta,va),(tb,vb),dt)) = plan.order.get(*i) {
+                        lines.push(match dt {
+                            Some(dt) => format!("train {} visit {} before train {} visit {} (within {:.0} s)", 
+                                                ta, va, tb, vb, dt),
+                            None => format!("train {} visit {} before train {} visit {}", ta, va, tb, vb),
+                        });
+                    }
+                }
+            },
+            None => {},
+        }
+        lines
+    }
+}
+
+fn planner_config(config :&PlanConfig) -> planner::input::Config {
+    planner::input::Config {
+        n_before: config.n_before as _, 
+        n_after: config.n_after as _, 
+        exact_n: config.exact_n.map(|n| n as _), 
+        optimize_signals: config.optimize_signals,
+    }
+}
+
+/// Run the planner and pass its candidates to `f` until `f` returns true or 
+/// the planner finishes. The planner also stops when the deadline has passed 
+/// or `cancel` is set, which is checked each time it finds a candidate. 
+/// Returns true if the planner was stopped by the deadline or `cancel`.
+fn run_planner(config :&planner::input::Config, inf :&planner::input::Infrastructure,
+               usage :&planner::input::Usage, deadline :Option<Instant>, cancel :&AtomicBool,
+               mut f :impl FnMut(&planner::input::RoutePlan) -> bool) -> bool {
+    let mut interrupted = false;
+    planner::solver::plan(config, inf, usage, |candidate| {
+        if f(candidate) { return true; }
+        interrupted = is_interrupted(deadline, cancel);
+        interrupted
+    });
+    interrupted
+}
+
+fn is_interrupted(deadline :Option<Instant>, cancel :&AtomicBool) -> bool {
+    cancel.load(Ordering::Relaxed) || deadline.map(|d| Instant::now() >= d).unwrap_or(false)
 }
 
 pub fn get_dispatches(
@@ -111,7 +516,8 @@ pub fn get_dispatches(
       il :&Interlocking, 
       vehicles :&[(usize,Vehicle)],
       plan :&PlanSpec,
-      ) -> Result<Vec<(Dispatch, History)>, String> {
+      cancel :&AtomicBool,
+      ) -> Result<(Vec<(Dispatch, History)>, PlanDiagnostics), String> {
 
     let routes : HashMap<usize,rolling_inf::Route> = 
         il.routes.iter().map(|r| r.route.clone()).enumerate().collect();
@@ -119,36 +525,270 @@ pub fn get_dispatches(
         il.routes.iter().map(|r| r.id.clone()).enumerate().collect();
 
     let plan_inf = convert_inf(&routes);
-    let plan_usage = convert_plan(il, vehicles, plan).
+    let plan_usage = convert_plan(dgraph, il, vehicles, plan).
         map_err(|e| format!("{:?}", e))?;
-    let config = planner::input::Config {
-        n_before: 3, n_after: 3, exact_n: None, optimize_signals: false,
-    };
+    let config = planner_config(&plan.config);
+    let deadline = plan.config.timeout.map(|t| Instant::now() + Duration::from_secs_f64(t.max(0.0)));
 
     //println!(" STARTIN GPLANNIGN");
     //println!("infrastructure {:#?}", plan_inf);
     //println!("usage {:#?}", plan_usage);
 
     let mut output = Vec::new();
-    planner::solver::plan(&config, &plan_inf, &plan_usage, |candidate| {
+    let mut diagnostics = PlanDiagnostics::default();
+    let timed_out = run_planner(&config, &plan_inf, &plan_usage, deadline, cancel, |candidate| {
         //println!("got one plan");
-        if let Ok((cmds,p)) = test_plan(dgraph, il, vehicles, plan, candidate).unwrap() {
-            let name = format!("Dispatch {}", output.len()+1);
-            output.push((Dispatch::from_vec(name,cmds),p));
+        diagnostics.candidates += 1;
+        let result = test_plan(dgraph, il, vehicles, plan, candidate)
+            .unwrap_or_else(|e| Err(TestPlanErr::Simulation(e)))
+            .and_then(|(cmds,history)| match plan.config.cycle_time {
+                Some(cycle) => match is_sustainable(dgraph, il, vehicles, &cmds, &history, cycle) {
+                    Ok(true) => Ok((cmds,history)),
+                    Ok(false) => Err(TestPlanErr::NotSustainable(cycle)),
+                    Err(e) => Err(TestPlanErr::Simulation(e)),
+                },
+                None => Ok((cmds,history)),
+            });
+        match result {
+            Ok((cmds,history)) => {
+                diagnostics.accepted += 1;
+                let key = dispatch_key(&cmds);
+                let value = objective_value(plan.config.objective, &cmds, &history);
+                match output.iter_mut().find(|(k,_,_,_)| *k == key) {
+                    Some(existing) => {
+                        diagnostics.merged += 1;
+                        if value < existing.1 { *existing = (key,value,cmds,history); }
+                    },
+                    None => { output.push((key,value,cmds,history)); },
+                }
+            },
+            Err(e) => {
+                let msg = e.message();
+                match diagnostics.rejections.iter_mut().find(|(m,_)| *m == msg) {
+                    Some((_,n)) => { *n += 1; },
+                    None => { diagnostics.rejections.push((msg,1)); },
+                }
+            },
+        }
+
+        if let Some(max) = plan.config.max_dispatches {
+            if output.len() >= max {
+                diagnostics.stopped = Some(format!("found {} dispatches", max));
+                return true;
+            }
         }
         false
     });
     //println!("planner finished");
-    Ok(output)
+
+    let timeout_msg = || format!("timeout after {:.1} s", plan.config.timeout.unwrap_or(0.0));
+    if timed_out {
+        diagnostics.stopped = Some(timeout_msg());
+    } else if diagnostics.candidates == 0 {
+        diagnostics.conflicting_order = conflicting_order(dgraph, il, vehicles, plan, &plan_inf, deadline, cancel)?;
+        if diagnostics.conflicting_order.is_none() {
+            diagnostics.stopped = Some(format!("{} while looking for conflicting ordering constraints", timeout_msg()));
+        }
+    }
+
+    // Sort by objective, and by key among equally good dispatches, so that
+    // the order does not depend on the order the solver finds them in.
+    output.sort_by(|(k1,v1,_,_),(k2,v2,_,_)| 
+                   v1.partial_cmp(v2).unwrap_or(std::cmp::Ordering::Equal).then(k1.cmp(k2)));
+    let output = output.into_iter().map(|(key,_,cmds,history)| {
+        (Dispatch::from_vec(dispatch_name(key), cmds), history)
+    }).collect();
+
+    Ok((output, diagnostics))
+}
+
+const PERIODIC_CYCLES :usize = 3;
+const CYCLE_TIME_PRECISION :f64 = 1.0;
+
+/// Repeat the commands `n` times, each repetition starting `cycle` seconds
+/// after the previous one.
+pub fn repeat_commands(commands :&Commands, cycle :f64, n :usize) -> Commands {
+    let mut repeated = Vec::new();
+    for i in 0..n {
+        for (_,(t,cmd)) in commands.iter() {
+            repeated.push((t + i as f64 * cycle, *cmd));
+        }
+    }
+    repeated.sort_by(|(t1,_),(t2,_)| t1.partial_cmp(t2).unwrap_or(std::cmp::Ordering::Equal));
+    repeated.into_iter().enumerate().map(|(i,c)| (i,c)).collect()
+}
+
+/// For each train created by the repeated commands, in the order they are
+/// created: the cycle it belongs to, and its index in the single cycle.
+pub fn repeated_trains(commands :&Commands, cycle :f64, n :usize) -> Vec<(usize,usize)> {
+    let starts = commands.iter().filter(|(_,(_,cmd))| matches!(cmd, Command::Train(_,_)))
+        .map(|(_,(t,_))| *t).collect::<Vec<_>>();
+    let mut trains = (0..n).flat_map(|k| starts.iter().enumerate()
+                                     .map(move |(j,t)| (t + k as f64 * cycle, k, j)))
+        .collect::<Vec<_>>();
+    trains.sort_by(|(t1,_,_),(t2,_,_)| t1.partial_cmp(t2).unwrap_or(std::cmp::Ordering::Equal));
+    trains.into_iter().map(|(_,k,j)| (k,j)).collect()
+}
+
+/// The start of each train's first movement and the end of its last movement.
+fn train_movements(history :&History) -> Vec<Option<(f64,f64)>> {
+    history.trains.iter().map(|(_,_,log)| {
+        let mut t = 0.0;
+        let mut movement = None;
+        for ev in log.iter() {
+            match ev {
+                TrainLogEvent::Wait(dt) => { t += dt; },
+                TrainLogEvent::Move(dt,_,DistanceVelocity { dx, .. }) => {
+                    if *dx > 0.0 {
+                        let start = movement.map(|(start,_)| start).unwrap_or(t);
+                        movement = Some((start, t + dt));
+                    }
+                    t += dt;
+                },
+                _ => {},
+            }
+        }
+        movement
+    }).collect()
+}
+
+/// Check whether a dispatch can be repeated with the given cycle time.
+/// The dispatch is simulated over a few cycles, and it is sustainable if
+/// every train in every cycle starts and finishes its movement at the same
+/// time, relative to its cycle, as in the single cycle. Then each cycle
+/// starts from the same state as the first one, and is not delayed by
+/// the trains of the cycle before.
+pub fn is_sustainable(dgraph :&DGraph, il :&Interlocking, vehicles :&[(usize,Vehicle)],
+                      commands :&Commands, history :&History, cycle :f64) -> Result<bool,String> {
+    let repeated = repeat_commands(commands, cycle, PERIODIC_CYCLES);
+    let (periodic,_) = history::get_history(vehicles, dgraph, il, &repeated, &Default::default())?;
+    Ok(cycles_match(&train_movements(history), &train_movements(&periodic),
+                    &repeated_trains(commands, cycle, PERIODIC_CYCLES), cycle))
+}
+
+/// Compare the movements of each train in the repeated cycles with the 
+/// corresponding train in the single cycle.
+fn cycles_match(single :&[Option<(f64,f64)>], periodic :&[Option<(f64,f64)>], 
+                trains :&[(usize,usize)], cycle :f64) -> bool {
+    let same = |a :f64, b :f64| (a - b).abs() <= CYCLE_TIME_PRECISION;
+    periodic.len() == trains.len() && trains.len() == PERIODIC_CYCLES * single.len() &&
+        trains.iter().zip(periodic.iter()).all(|((k,j),movement)| {
+            let offset = *k as f64 * cycle;
+            match (single.get(*j), movement) {
+                (Some(Some((s1,e1))), Some((s2,e2))) => same(s1 + offset, *s2) && same(e1 + offset, *e2),
+                (Some(None), None) => true,
+                _ => false,
+            }
+        })
+}
+
+/// The shortest cycle time the dispatch can be repeated with. A cycle time
+/// can be sustainable even if a longer one is not, so instead of searching,
+/// cycle times are tried in steps of `CYCLE_TIME_PRECISION` up to the single
+/// cycle's completion time, and the first sustainable one is returned.
+/// Returns `None` if none of them are sustainable.
+pub fn min_cycle_time(dgraph :&DGraph, il :&Interlocking, vehicles :&[(usize,Vehicle)],
+                      commands :&Commands, history :&History) -> Result<Option<f64>,String> {
+    let completion = crate::document::dispatch::max_time(history);
+    let steps = (completion / CYCLE_TIME_PRECISION).ceil() as usize;
+    for i in 1..=steps {
+        let cycle = (i as f64 * CYCLE_TIME_PRECISION).min(completion);
+        if is_sustainable(dgraph, il, vehicles, commands, history, cycle)? { 
+            return Ok(Some(cycle)); 
+        }
+    }
+    Ok(None)
+}
+
+/// Identity of a dispatch up to timing: the sequence of commands without
+/// their times. This is used for merging dispatches from the planner and
+/// for keeping a selected dispatch when the plan is analyzed again.
+pub fn dispatch_key(commands :&Commands) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for (_,(_,cmd)) in commands.iter() {
+        cmd.hash(&mut hasher);
+    }
+    hasher.finish()
 }
 
+/// Name of a planned dispatch, derived from its `dispatch_key` so that
+/// it does not change when the dispatches are ranked differently.
+pub fn dispatch_name(key :u64) -> String {
+    format!("Dispatch {:06x}", key >> 40)
+}
+
+pub fn objective_value(objective :PlanObjective, commands :&Commands, history :&History) -> f64 {
+    let train_time = |waiting_only :bool| {
+        let mut sum = 0.0;
+        for (_,_,log) in history.trains.iter() {
+            for ev in log.iter() {
+                match ev {
+                    TrainLogEvent::Wait(dt) => { sum += dt; },
+                    TrainLogEvent::Move(dt,_,DistanceVelocity { dx, .. }) 
+                        if !waiting_only || !(*dx > 0.0) => { sum += dt; },
+                    _ => {},
+                }
+            }
+        }
+        sum
+    };
 
+    match objective {
+        PlanObjective::CompletionTime => crate::document::dispatch::max_time(history),
+        PlanObjective::RunningTime => train_time(false),
+        PlanObjective::RouteCommands => commands.len() as f64,
+        PlanObjective::WaitingTime => train_time(true),
+    }
+}
+
+/// Find a minimal set of ordering constraints which makes the plan
+/// infeasible for the planner, by removing one constraint at a time and
+/// keeping it only if the plan becomes feasible without it. Returns `None`
+/// if the deadline passes or `cancel` is set before the set is found.
+fn conflicting_order(dgraph :&DGraph, il :&Interlocking, vehicles :&[(usize,Vehicle)],
+                     plan :&PlanSpec, plan_inf :&planner::input::Infrastructure, 
+                     deadline :Option<Instant>, cancel :&AtomicBool) -> Result<Option<Vec<usize>>,String> {
+    let feasible = |order :&[usize]| -> Result<Option<bool>,String> {
+        if is_interrupted(deadline, cancel) { return Ok(None); }
+        let mut subplan = plan.clone();
+        subplan.order = order.iter().map(|i| plan.order[*i].clone()).collect();
+        let usage = convert_plan(dgraph, il, vehicles, &subplan).map_err(|e| format!("{:?}", e))?;
+        let mut found = false;
+        let timed_out = run_planner(&planner_config(&plan.config), plan_inf, &usage, deadline, cancel,
+                                    |_| { found = true; true });
+        Ok(if timed_out { None } else { Some(found) })
+    };
+
+    match feasible(&[])? {
+        None => return Ok(None),
+        Some(false) => return Ok(Some(Vec::new())),
+        Some(true) => {},
+    }
+
+    let mut set :Vec<usize> = (0..plan.order.len()).collect();
+    let mut i = 0;
+    while i < set.len() {
+        let without = set.iter().enumerate().filter(|(j,_)| *j != i).map(|(_,x)| *x).collect::<Vec<_>>();
+        match feasible(&without)? {
+            None => return Ok(None),
+            Some(true) => { i += 1; }, // needed for the conflict
+            Some(false) => { set = without; },
+        }
+    }
+    Ok(Some(set))
+}
+
+
+/// Convert the planner's route plan into dispatch commands, all at time zero.
+/// Also returns the plan's train id for each command.
 fn convert_dispatch_commands(routeplan :&planner::input::RoutePlan, il :&Interlocking,
-                          plan :&PlanSpec) -> Result<Commands,String> {
+                          plan :&PlanSpec) -> Result<(Commands, Vec<usize>),String> {
 
     use std::collections::BTreeSet;
 
     let mut commands = Vec::new();
+    let mut command_trains = Vec::new();
     let mut last_active_routes = BTreeSet::new();
 
     for state in routeplan.iter() {
@@ -167,10 +807,12 @@ fn convert_dispatch_commands(routeplan :&planner::input::RoutePlan, il :&Interlo
                     commands.push((0.0, Command::Train(
                                 plan.trains.get(*train_id).unwrap().0.unwrap(), //vehicle id
                                 il.routes[*new_route].id)));
+                    command_trains.push(*train_id);
                 },
                 rolling_inf::RouteEntryExit::Signal(_) 
                     | rolling_inf::RouteEntryExit::SignalTrigger { .. } => {
                         commands.push((0.0, Command::Route(il.routes[*new_route].id)));
+                        command_trains.push(*train_id);
                 },
             }
         }
@@ -178,7 +820,7 @@ fn convert_dispatch_commands(routeplan :&planner::input::RoutePlan, il :&Interlo
         last_active_routes = active_routes;
     }
 
-    Ok(commands.into_iter().enumerate().collect())
+    Ok((commands.into_iter().enumerate().collect(), command_trains))
 }
 
 
@@ -186,7 +828,7 @@ pub fn convert_inf(routes :&rolling_inf::Routes<usize>) -> planner::input::Infra
 
     let mut partial_routes = HashMap::new();
     let mut elementary_routes = Vec::new();
-    let mut partial_route_resources :HashMap<usize, HashSet<planner::input::PartialRouteId>> = HashMap::new();
+    let mut partial_route_resources :HashMap<usize, HashSet<(planner::input::PartialRouteId,usize)>> = HashMap::new();
     let mut fresh = { let mut i = 0; move || { i += 1; i } };
 
     fn convert_routeentryexit(e :&rolling_inf::RouteEntryExit) -> planner::input::SignalId {
@@ -223,11 +865,10 @@ pub fn convert_inf(routes :&rolling_inf::Routes<usize>) -> planner::input::Infra
 
         let mut elementary_route = HashSet::new();
         for (i,(entry,exit)) in signals.iter().zip(signals.iter().skip(1)).enumerate() {
-            let (length,resources) = if route.resources.releases.len() > 0 {
-                let release = route.resources.releases[i].clone();
-                (release.length, release.resources)
+            let length = if route.resources.releases.len() > 0 {
+                route.resources.releases[i].length
             } else {
-                (route.length, std::iter::empty().collect())
+                route.length
             };
 
             partial_routes.insert((*route_name, i), planner::input::PartialRoute {
@@ -237,36 +878,34 @@ pub fn convert_inf(routes :&rolling_inf::Routes<usize>) -> planner::input::Infra
                 length: length as _,
             });
 
-            for resource in resources.iter() {
-                partial_route_resources.entry(*resource)
-                    .or_insert(HashSet::new())
-                    .insert((*route_name, i));
+            for (choice,resources) in partial_route_choices(route, i).into_iter().enumerate() {
+                for resource in resources {
+                    partial_route_resources.entry(resource)
+                        .or_insert(HashSet::new())
+                        .insert(((*route_name, i), choice));
+                }
             }
             elementary_route.insert((*route_name, i));
         }
         elementary_routes.push(elementary_route);
     }
 
-    // second pass adds conflicting routes from resource -> partialroutes map
+    // second pass adds conflicting routes from resource -> partialroutes map,
+    // one set of conflicts for each overlap choice
     for (rn,r) in routes.iter() {
-        if r.resources.releases.len() > 0 {
-            for (i,rel) in r.resources.releases.iter().enumerate() {
+        for i in 0..(r.resources.releases.len().max(1)) {
+            let conflicts = partial_route_choices(r, i).into_iter().map(|resources| {
                 let mut conflicting_routes = HashSet::new();
-                for resource in rel.resources.iter() {
+                for resource in resources.iter() {
                     if let Some(conflicts) = partial_route_resources.get(resource) {
                         conflicting_routes.extend(conflicts.iter().cloned()
-                                                  .filter(|(pr_e,pr_p)| pr_e != rn)
-                                                  .map(|pr| (pr,0)));
+                                                  .filter(|((pr_e,_),_)| pr_e != rn));
                     }
                 }
+                conflicting_routes
+            }).collect();
 
-                partial_routes.get_mut(&(*rn,i)).unwrap().conflicts =
-                    vec![conflicting_routes]; // TODO overlap alternatives
-            }
-        } else {
-            // There are no resources, but we have to add the overlap choice anyway
-            partial_routes.get_mut(&(*rn,0)).unwrap().conflicts = 
-                vec![std::iter::empty().collect()];
+            partial_routes.get_mut(&(*rn,i)).unwrap().conflicts = conflicts;
         }
     }
 
@@ -289,8 +928,30 @@ pub fn convert_inf(routes :&rolling_inf::Routes<usize>) -> planner::input::Infra
     planner::input::Infrastructure { partial_routes, elementary_routes }
 }
 
+/// Resources used by a partial route, for each of its overlap choices.
+/// Only the last partial route of a route has alternative overlaps,
+/// the others have a single choice. A partial route without any
+/// resources still gets one (empty) choice.
+fn partial_route_choices(route :&rolling_inf::Route, i :usize) -> Vec<Vec<rolling_inf::ObjectId>> {
+    let resources :Vec<rolling_inf::ObjectId> = route.resources.releases.get(i)
+        .map(|r| r.resources.iter().cloned().collect())
+        .unwrap_or(Vec::new());
+    let is_last = i + 1 == route.resources.releases.len().max(1);
+    if is_last && route.overlaps.len() > 0 {
+        route.overlaps.iter().map(|overlap| {
+            let mut choice = resources.clone();
+            choice.extend(overlap.sections.iter().cloned());
+            choice.extend(overlap.switch_positions.iter().map(|(sw,_)| *sw));
+            choice
+        }).collect()
+    } else {
+        vec![resources]
+    }
+}
+
 
-pub fn convert_plan(il :&Interlocking, 
+pub fn convert_plan(dgraph :&DGraph,
+                    il :&Interlocking, 
                     vehicles :&[(usize,Vehicle)], 
                     plan :&PlanSpec) -> Result<planner::input::Usage, ConvertPlanErr> {
 
@@ -300,14 +961,24 @@ pub fn convert_plan(il :&Interlocking,
         let vehicle = vehicles.iter().find(|(i,v)| *i == vehicle_id).map(|(i,v)| v)
             .ok_or(ConvertPlanErr::VehicleMissing)?;
         let mut planner_visits :Vec<HashSet<usize>> = Vec::new();
+        // Dwell times are not part of the planner's problem. They are added
+        // afterwards by delaying route commands, see `test_plan`.
         for (visit_i, (visit_id, Visit { locs, dwell})) in visits.iter().enumerate() {
             let mut set = HashSet::new();
             let bdry = if visit_i == 0 { &il.boundary_routes } else { &il.boundary_out_routes };
             for (loc_i, loc) in locs.iter().enumerate() {
-                if let Ok(Ref::Node(pt)) = loc {
-                    set.extend(bdry.get(pt).into_iter().flat_map(move |rs| rs.iter()));
+                let boundary_routes = match loc {
+                    Ok(Ref::Node(pt)) => bdry.get(pt),
+                    _ => None,
+                };
+                if let Some(rs) = boundary_routes {
+                    set.extend(rs.iter());
                 }  else {
-                    unimplemented!(); // TODO  other types of infrastructure references
+                    // Any route passing the location can be used to visit it.
+                    let m = location_match(dgraph, loc);
+                    set.extend(il.routes.iter().enumerate()
+                               .filter(|(_,r)| m.matches_path(&r.path))
+                               .map(|(i,_)| i));
                 }
             }
             planner_visits.push(set);
@@ -320,7 +991,8 @@ pub fn convert_plan(il :&Interlocking,
 
     let mut train_ord = Vec::new();
     for ((train_a,visit_a),(train_b,visit_b), _max_time) in &plan.order {
-        // TODO max_time between visits
+        // The planner only knows the order of visits. The maximum time 
+        // between visits is handled when testing candidates, see `test_plan`.
         let visit_idx = |train_id, visit_id| plan.trains.get(train_id).unwrap()
             .1.iter().position(|(v,_)| v == visit_id).unwrap(); 
         // TODO unwrap crashes if visit_id is missing
@@ -338,3 +1010,153 @@ pub fn convert_plan(il :&Interlocking,
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{topology, objects::*, dgraph::DGraphBuilder};
+
+    /// A straight track from (0,0) to (2,0) with a detector at (0.5,0).
+    fn straight_track() -> DGraph {
+        let mut m = Model::empty();
+        m.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        m.linesegs.insert((glm::vec2(1,0), glm::vec2(2,0)));
+        m.objects.insert(glm::vec2(5,0), Object { loc: glm::vec2(0.5,0.0), tangent: glm::vec2(1,0),
+                                                  functions: vec![Function::Detector] });
+        DGraphBuilder::convert(&topology::convert(&m, 50.0).unwrap()).unwrap()
+    }
+
+    #[test]
+    pub fn location_nodes() {
+        let dgraph = straight_track();
+        let boundary = location_match(&dgraph, &Ok(Ref::Node(glm::vec2(0,0))));
+        assert_eq!(boundary.nodes.len(), 2);
+        assert!(boundary.edges.is_empty());
+
+        let detector = location_match(&dgraph, &Ok(Ref::Object(glm::vec2(5,0))));
+        assert_eq!(detector.nodes.len(), 2);
+        assert!(detector.nodes.iter().all(|n| !boundary.nodes.contains(n)));
+        let locs = vec![detector];
+        assert!(locs[0].nodes.iter().all(|n| event_matches_spec(&locs, &TrainLogEvent::Node(*n))));
+        assert!(boundary.nodes.iter().all(|n| !event_matches_spec(&locs, &TrainLogEvent::Node(*n))));
+    }
+
+    #[test]
+    pub fn location_edges() {
+        let dgraph = straight_track();
+        let seg = location_match(&dgraph, &Ok(Ref::LineSeg(glm::vec2(1,0), glm::vec2(2,0))));
+        assert!(seg.nodes.is_empty());
+        assert!(!seg.edges.is_empty());
+        assert!(seg.edges.iter().all(|(a,b)| seg.edges.contains(&(*b,*a))));
+
+        // A point matches the single closest edge, in both directions.
+        let pt = location_match(&dgraph, &Err(glm::vec2(1.5,0.1)));
+        assert_eq!(pt.edges.len(), 2);
+        assert!(pt.edges.iter().all(|e| seg.edges.contains(e)));
+
+        let locs = vec![pt];
+        assert!(locs[0].edges.iter().all(|(a,b)| event_matches_spec(&locs, &TrainLogEvent::Edge(*a, Some(*b)))));
+
+        let path = locs[0].edges.iter().cloned().take(1).collect::<Vec<_>>();
+        assert!(seg.matches_path(&path));
+        assert!(!seg.matches_path(&[(1000,1001)]));
+    }
+
+    fn route(a :i32, b :i32) -> RouteSpec {
+        RouteSpec { from: Ref::Node(glm::vec2(a,0)), to: Ref::Node(glm::vec2(b,0)), alternative: 0 }
+    }
+
+    #[test]
+    pub fn dispatch_key_ignores_timing() {
+        let cmds = |t :&[f64]| -> Commands { vec![
+            (0, (t[0], Command::Train(1, route(0,1)))),
+            (1, (t[1], Command::Route(route(1,2)))),
+        ] };
+        assert_eq!(dispatch_key(&cmds(&[0.0, 10.0])), dispatch_key(&cmds(&[5.0, 42.0])));
+
+        let mut reordered = cmds(&[0.0, 10.0]);
+        reordered.reverse();
+        assert_ne!(dispatch_key(&cmds(&[0.0, 10.0])), dispatch_key(&reordered));
+
+        let other = vec![(0, (0.0, Command::Train(2, route(0,1)))), (1, (10.0, Command::Route(route(1,2))))];
+        assert_ne!(dispatch_key(&cmds(&[0.0, 10.0])), dispatch_key(&other));
+    }
+
+    #[test]
+    pub fn repeated_train_order() {
+        let cmds :Commands = vec![
+            (0, (0.0, Command::Train(1, route(0,1)))),
+            (1, (20.0, Command::Route(route(1,2)))),
+            (2, (50.0, Command::Train(1, route(2,1)))),
+        ];
+        assert_eq!(repeated_trains(&cmds, 100.0, 2), vec![(0,0),(0,1),(1,0),(1,1)]);
+        assert_eq!(repeated_trains(&cmds, 30.0, 2), vec![(0,0),(1,0),(0,1),(1,1)]);
+        assert_eq!(repeat_commands(&cmds, 30.0, 2).iter().map(|(_,(t,_))| *t).collect::<Vec<_>>(),
+                   vec![0.0, 20.0, 30.0, 50.0, 50.0, 80.0]);
+    }
+
+    #[test]
+    pub fn cycle_comparison() {
+        let single = vec![Some((0.0, 100.0)), Some((50.0, 120.0))];
+        let trains = repeated_trains(&vec![
+            (0, (0.0, Command::Train(1, route(0,1)))),
+            (1, (50.0, Command::Train(1, route(2,1)))),
+        ], 200.0, PERIODIC_CYCLES);
+        let periodic = (0..PERIODIC_CYCLES).flat_map(|k| single.iter()
+            .map(move |m| m.map(|(s,e)| (s + k as f64 * 200.0, e + k as f64 * 200.0))))
+            .collect::<Vec<_>>();
+        assert!(cycles_match(&single, &periodic, &trains, 200.0));
+
+        // A train in a later cycle is delayed by the cycle before.
+        let mut delayed = periodic.clone();
+        delayed[2] = Some((210.0, 310.0));
+        assert!(!cycles_match(&single, &delayed, &trains, 200.0));
+
+        // A train is missing.
+        assert!(!cycles_match(&single, &periodic[1..], &trains, 200.0));
+    }
+
+    #[test]
+    pub fn dwell_passing_through() {
+        // Waiting at a red signal before the station does not count.
+        let mut d = DwellCheck::default();
+        d.drive(200.0);
+        d.stand(60.0);
+        d.drive(500.0);
+        d.arrive(0, 100.0, 30.0);
+        d.drive(200.0);
+        let (visit, time, missing) = d.leave().unwrap();
+        assert_eq!((visit, time), (0, 100.0));
+        assert!((missing - 30.0).abs() < 1e-6);
+
+        // Neither does waiting after the train has left the station.
+        let mut d = DwellCheck::default();
+        d.arrive(0, 100.0, 30.0);
+        d.drive(200.0);
+        assert!(d.leave().is_some());
+        d.stand(60.0);
+        assert!(d.leave().is_none());
+    }
+
+    #[test]
+    pub fn dwell_stopping() {
+        // Stopping in front of a signal at the location.
+        let mut d = DwellCheck::default();
+        d.drive(500.0);
+        d.stand(20.0);
+        d.drive(0.5);
+        d.stand(15.0);
+        d.arrive(0, 100.0, 30.0);
+        d.drive(200.0);
+        assert!(d.leave().is_none());
+
+        // Stopping after reaching the location, before leaving it.
+        let mut d = DwellCheck::default();
+        d.drive(500.0);
+        d.arrive(0, 100.0, 30.0);
+        d.drive(150.0);
+        d.stand(20.0);
+        let (_, _, missing) = d.leave().unwrap();
+        assert!((missing - 10.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::document::model::*;
+
+/// Typical operational patterns that can be used as a starting point for
+/// a plan. Boundaries are model boundary nodes, and the station is any
+/// location the trains should pass through.
+#[derive(Debug, Clone)]
+pub enum PlanTemplate {
+    /// Two trains in opposite directions meet at a station on a single-track line.
+    Crossing { a :Pt, b :Pt, station :Ref, vehicles :[Option<ListId>;2] },
+    /// The second train overtakes the first train at a station.
+    Overtaking { a :Pt, b :Pt, station :Ref, vehicles :[Option<ListId>;2] },
+    /// The second train follows the first train, entering `headway` seconds after it.
+    Following { a :Pt, b :Pt, headway :f64, vehicles :[Option<ListId>;2] },
+    /// A number of trains per hour, evenly spaced, taking turns on the given boundary pairs.
+    TrainsPerHour { routes :Vec<(Pt,Pt)>, trains_per_hour :usize, vehicle :Option<ListId> },
+}
+
+impl PlanTemplate {
+    pub const NAMES :[&'static str; 4] = ["Crossing", "Overtaking", "Following", "Trains per hour"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.index()]
+    }
+
+    /// Position of the template kind in `NAMES`.
+    pub fn index(&self) -> usize {
+        match self {
+            PlanTemplate::Crossing { .. } => 0,
+            PlanTemplate::Overtaking { .. } => 1,
+            PlanTemplate::Following { .. } => 2,
+            PlanTemplate::TrainsPerHour { .. } => 3,
+        }
+    }
+
+    pub fn plan(&self, name :String) -> PlanSpec {
+        let mut plan = PlanSpec::new_empty(name);
+        match self {
+            PlanTemplate::Crossing { a, b, station, vehicles } => {
+                let (t1,v1) = add_train(&mut plan, vehicles[0], &[Ref::Node(*a), *station, Ref::Node(*b)]);
+                let (t2,v2) = add_train(&mut plan, vehicles[1], &[Ref::Node(*b), *station, Ref::Node(*a)]);
+                // Both trains reach the station before the other one leaves the line.
+                plan.order.push(((t1,v1[1]),(t2,v2[2]),None));
+                plan.order.push(((t2,v2[1]),(t1,v1[2]),None));
+            },
+            PlanTemplate::Overtaking { a, b, station, vehicles } => {
+                let (t1,v1) = add_train(&mut plan, vehicles[0], &[Ref::Node(*a), *station, Ref::Node(*b)]);
+                let (t2,v2) = add_train(&mut plan, vehicles[1], &[Ref::Node(*a), *station, Ref::Node(*b)]);
+                // The first train enters first, and leaves last.
+                plan.order.push(((t1,v1[0]),(t2,v2[0]),None));
+                plan.order.push(((t1,v1[1]),(t2,v2[1]),None));
+                plan.order.push(((t2,v2[2]),(t1,v1[2]),None));
+            },
+            PlanTemplate::Following { a, b, headway, vehicles } => {
+                let (t1,v1) = add_train(&mut plan, vehicles[0], &[Ref::Node(*a), Ref::Node(*b)]);
+                let (t2,v2) = add_train(&mut plan, vehicles[1], &[Ref::Node(*a), Ref::Node(*b)]);
+                plan.order.push(((t1,v1[0]),(t2,v2[0]),Some(*headway)));
+                plan.min_times.push(((t1,v1[0]),(t2,v2[0]),*headway));
+                plan.order.push(((t1,v1[1]),(t2,v2[1]),None));
+            },
+            PlanTemplate::TrainsPerHour { routes, trains_per_hour, vehicle } => {
+                if routes.is_empty() || *trains_per_hour == 0 { return plan; }
+                let interval = 3600.0 / *trains_per_hour as f64;
+                let mut prev = None;
+                for i in 0..*trains_per_hour {
+                    let (from,to) = routes[i % routes.len()];
+                    let (t,v) = add_train(&mut plan, *vehicle, &[Ref::Node(from), Ref::Node(to)]);
+                    if let Some(prev) = prev {
+                        plan.order.push((prev,(t,v[0]),Some(interval)));
+                        plan.min_times.push((prev,(t,v[0]),interval));
+                    }
+                    prev = Some((t,v[0]));
+                }
+            },
+        }
+        plan
+    }
+}
+
+/// Add a train visiting each of the locations in order. Returns the train
+/// id and the visit ids.
+fn add_train(plan :&mut PlanSpec, vehicle :Option<ListId>, locs :&[Ref]) -> (ListId, Vec<ListId>) {
+    let mut visits = ImShortGenList::new();
+    let visit_ids = locs.iter().map(|r| visits.insert(Visit { locs: vec![Ok(*r)], dwell: None })).collect();
+    let train_id = plan.trains.insert((vehicle, visits));
+    (train_id, visit_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm as glm;
+
+    fn train_locs(plan :&PlanSpec) -> Vec<Vec<Ref>> {
+        plan.trains.iter().map(|(_,(_,visits))| visits.iter()
+                               .map(|(_,v)| v.locs[0].clone().unwrap()).collect()).collect()
+    }
+
+    #[test]
+    pub fn names() {
+        let (a,b) = (glm::vec2(0,0), glm::vec2(10,0));
+        let templates = [
+            PlanTemplate::Crossing { a, b, station: Ref::Node(glm::vec2(5,0)), vehicles: [None,None] },
+            PlanTemplate::Overtaking { a, b, station: Ref::Node(glm::vec2(5,0)), vehicles: [None,None] },
+            PlanTemplate::Following { a, b, headway: 120.0, vehicles: [None,None] },
+            PlanTemplate::TrainsPerHour { routes: vec![(a,b)], trains_per_hour: 2, vehicle: None },
+        ];
+        for (i,t) in templates.iter().enumerate() {
+            assert_eq!(t.index(), i);
+            assert_eq!(t.name(), PlanTemplate::NAMES[i]);
+        }
+    }
+
+    #[test]
+    pub fn crossing() {
+        let (a,b,s) = (glm::vec2(0,0), glm::vec2(10,0), Ref::Node(glm::vec2(5,0)));
+        let plan = PlanTemplate::Crossing { a, b, station: s, vehicles: [None,None] }.plan(format!("c"));
+        assert_eq!(train_locs(&plan), vec![vec![Ref::Node(a), s, Ref::Node(b)],
+                                           vec![Ref::Node(b), s, Ref::Node(a)]]);
+        assert_eq!(plan.order.len(), 2);
+        assert!(plan.min_times.is_empty());
+    }
+
+    #[test]
+    pub fn following_headway() {
+        let (a,b) = (glm::vec2(0,0), glm::vec2(10,0));
+        let plan = PlanTemplate::Following { a, b, headway: 120.0, vehicles: [None,None] }.plan(format!("f"));
+        assert_eq!(plan.trains.iter().count(), 2);
+        let (first, second, max) = plan.order[0];
+        assert_eq!(max, Some(120.0));
+        assert_eq!(plan.min_times, vec![(first, second, 120.0)]);
+    }
+
+    #[test]
+    pub fn trains_per_hour_spacing() {
+        let (a,b,c) = (glm::vec2(0,0), glm::vec2(10,0), glm::vec2(10,5));
+        let plan = PlanTemplate::TrainsPerHour { routes: vec![(a,b),(a,c)], trains_per_hour: 4, vehicle: None }
+            .plan(format!("t"));
+        let locs = train_locs(&plan);
+        assert_eq!(locs.len(), 4);
+        assert_eq!(locs[0], vec![Ref::Node(a), Ref::Node(b)]);
+        assert_eq!(locs[1], vec![Ref::Node(a), Ref::Node(c)]);
+        assert_eq!(locs[2], locs[0]);
+        assert_eq!(plan.order.len(), 3);
+        assert!(plan.order.iter().all(|(_,_,dt)| *dt == Some(900.0)));
+        assert_eq!(plan.min_times.len(), 3);
+        for ((a1,b1,_),(a2,b2,dt)) in plan.order.iter().zip(plan.min_times.iter()) {
+            assert_eq!((a1,b1), (a2,b2));
+            assert_eq!(*dt, 900.0);
+        }
+
+        let empty = PlanTemplate::TrainsPerHour { routes: vec![], trains_per_hour: 4, vehicle: None }
+            .plan(format!("e"));
+        assert!(empty.trains.iter().next().is_none());
+    }
+}
@@ -0,0 +1,173 @@
+// Route table export: one row per route in the interlocking, written as
+// CSV for further processing or as a Markdown table for reading.
+
+use rolling::input::staticinfrastructure as rolling_inf;
+use crate::document::model::Ref;
+use crate::document::dgraph::*;
+use crate::document::interlocking::*;
+use crate::document::issues::ref_name;
+
+#[derive(Debug, Clone)]
+pub struct RouteTableRow {
+    pub id :usize,
+    pub entry :String,
+    pub exit :String,
+    pub alternative :usize,
+    pub length :f64,
+    pub switches :Vec<String>,
+    pub sections :Vec<String>,
+    pub releases :Vec<String>,
+    pub overlaps :Vec<String>,
+    pub flank :Vec<String>,
+}
+
+const HEADER :&[&str] = &["Route", "Entry", "Exit", "Alternative", "Length (m)",
+    "Switch positions", "Sections", "Releases", "Overlaps", "Flank protection"];
+
+pub fn rows(dgraph :&DGraph, il :&Interlocking) -> Vec<RouteTableRow> {
+    il.routes.iter().enumerate().map(|(idx,info)| {
+        let route = &info.route;
+        RouteTableRow {
+            id: idx,
+            entry: entry_exit_name(dgraph, &route.entry),
+            exit: entry_exit_name(dgraph, &route.exit),
+            alternative: info.id.alternative,
+            length: route.length,
+            switches: route.resources.switch_positions.iter()
+                .filter(|(sw,_)| !info.flank.switches.iter().any(|(x,_)| x == sw))
+                .map(|(sw,pos)| switch_name(dgraph, *sw, *pos)).collect(),
+            sections: route.resources.sections.iter()
+                .map(|tvd| section_name(dgraph, *tvd)).collect(),
+            releases: route.resources.releases.iter().map(|r| {
+                let end = r.end_node.and_then(|n| dgraph.detector_ids.get_by_left(&n).or_else(||
+                            dgraph.detector_ids.get_by_left(&dgraph.rolling_inf.nodes[n].other_node)))
+                    .map(|pta| ref_name(&Ref::Object(*pta)))
+                    .unwrap_or_else(|| format!("route exit"));
+                format!("{} at {} ({:.0} m)", section_name(dgraph, r.trigger), end, r.length)
+            }).collect(),
+            overlaps: route.overlaps.iter().map(|o| {
+                o.sections.iter().map(|tvd| section_name(dgraph, *tvd))
+                    .chain(o.switch_positions.iter().map(|(sw,pos)| switch_name(dgraph, *sw, *pos)))
+                    .collect::<Vec<_>>().join(" + ")
+            }).collect(),
+            flank: info.flank.switches.iter().map(|(sw,pos)| switch_name(dgraph, *sw, *pos))
+                .chain(info.flank.signals.iter().map(|s| entry_exit_name(dgraph, 
+                                                     &rolling_inf::RouteEntryExit::Signal(*s))))
+                .collect(),
+        }
+    }).collect()
+}
+
+fn entry_exit_name(dgraph :&DGraph, e :&rolling_inf::RouteEntryExit) -> String {
+    let kind = match e {
+        rolling_inf::RouteEntryExit::Boundary(_) => "boundary",
+        _ => "signal",
+    };
+    match entry_exit_ref(dgraph, e) {
+        Some(r) => format!("{} {}", kind, ref_name(&r)),
+        None => format!("{}", kind),
+    }
+}
+
+fn switch_name(dgraph :&DGraph, sw :rolling_inf::ObjectId, pos :rolling_inf::SwitchPosition) -> String {
+    let pos = match pos {
+        rolling_inf::SwitchPosition::Left => "left",
+        rolling_inf::SwitchPosition::Right => "right",
+    };
+    match dgraph.switch_ids.get_by_left(&sw) {
+        Some(pt) => format!("switch ({},{}) {}", pt.x, pt.y, pos),
+        None => format!("switch #{} {}", sw, pos),
+    }
+}
+
+pub fn section_name(dgraph :&DGraph, tvd :rolling_inf::ObjectId) -> String {
+    match section_ref(dgraph, tvd) {
+        Some(r) => format!("section at {}", ref_name(&r)),
+        None => format!("section #{}", tvd),
+    }
+}
+
+fn row_fields(row :&RouteTableRow, list_sep :&str) -> Vec<String> {
+    vec![format!("{}", row.id), row.entry.clone(), row.exit.clone(),
+         format!("{}", row.alternative), format!("{:.1}", row.length),
+         row.switches.join(list_sep), row.sections.join(list_sep),
+         row.releases.join(list_sep), row.overlaps.join(list_sep),
+         row.flank.join(list_sep)]
+}
+
+pub fn csv_field(s :&str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn to_csv(rows :&[RouteTableRow]) -> String {
+    let mut out = String::new();
+    out.push_str(&HEADER.join(","));
+    out.push('\n');
+    for row in rows {
+        let fields = row_fields(row, "; ").iter().map(|f| csv_field(f)).collect::<Vec<_>>();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn to_markdown(rows :&[RouteTableRow]) -> String {
+    let mut out = String::new();
+    out.push_str("# Route table\n\n");
+    out.push_str(&format!("| {} |\n", HEADER.join(" | ")));
+    out.push_str(&format!("|{}\n", HEADER.iter().map(|_| "---|").collect::<String>()));
+    for row in rows {
+        let fields = row_fields(row, "<br>").iter()
+            .map(|f| f.replace('|', "\\|")).collect::<Vec<_>>();
+        out.push_str(&format!("| {} |\n", fields.join(" | ")));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row() -> RouteTableRow {
+        RouteTableRow {
+            id: 0,
+            entry: "boundary (0,0)".to_string(),
+            exit: "signal \"A\", north".to_string(),
+            alternative: 1,
+            length: 250.0,
+            switches: vec!["switch (1,0) left".to_string(), "switch (2,0) right".to_string()],
+            sections: vec![],
+            releases: vec!["a|b".to_string()],
+            overlaps: vec![],
+            flank: vec![],
+        }
+    }
+
+    #[test]
+    pub fn csv_escaping() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+
+        let csv = to_csv(&[row()]);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], HEADER.join(","));
+        assert_eq!(lines[1], "0,\"boundary (0,0)\",\"signal \"\"A\"\", north\",1,250.0,\
+                              \"switch (1,0) left; switch (2,0) right\",,a|b,,");
+    }
+
+    #[test]
+    pub fn markdown_escaping() {
+        let md = to_markdown(&[row()]);
+        let last = md.lines().last().unwrap();
+        assert!(last.contains("switch (1,0) left<br>switch (2,0) right"));
+        assert!(last.contains("a\\|b"));
+        assert_eq!(last.matches(" | ").count(), HEADER.len() - 1);
+    }
+}
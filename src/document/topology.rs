@@ -3,9 +3,11 @@ use nalgebra_glm as glm;
 
 use crate::document::model::*;
 use crate::document::objects::*;
+use crate::document::issues::Issue;
 
 use crate::util::*;
 use ordered_float::OrderedFloat;
+use matches::matches;
 
 
 #[derive(Clone)]
@@ -15,6 +17,14 @@ pub struct Topology {
     pub locations : HashMap<Pt,(NDType,Vc)>,
     pub trackobjects : Vec<Vec<(f64,PtA, Function,Option<AB>)>>,
     pub interval_lines :Vec<Vec<(OrderedFloat<f64>,PtC)>>,
+    /// Mileages pinned on nodes and objects.
+    pub mileages :Vec<(Ref,f64)>,
+    /// Speed restrictions (km/h) on switches and crossings.
+    pub node_speeds :HashMap<Pt,u32>,
+    /// Gradients (per mille) on each track, as intervals of the track's
+    /// length, rising in the track's direction.
+    pub gradients :Vec<Vec<(f64,f64,f64)>>,
+    pub issues :Vec<Issue>,
 }
 
 impl Topology {
@@ -55,10 +65,12 @@ impl Topology {
 
 
 //pub fn convert(model :&Model, def_len :f64) -> Result<(Tracks,Locations,TrackObjects,im::HashMap<Pt,NDType>), ()>{
-pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
+pub fn convert(model :&Model, def_len :f64) -> Result<Topology, Issue>{
 
     type TrackEnd = (usize, AB);
 
+    let mut issues = overlapping_segments(model);
+
     let mut tracks :Vec<(Pt,Pt,f64)> = Vec::new();
     let mut locs :HashMap<(i32,i32), Vec<(TrackEnd,Pt)>> = HashMap::new();
     let mut interval_lines = Vec::new();
@@ -70,6 +82,7 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
 
     let mut piece_map : HashMap<((i32,i32),(i32,i32)), (usize, f64, f64)> = HashMap::new();
     let mut trackobjects = Vec::new();
+    let mut gradients = Vec::new();
     while let Some((p1,p2)) = pieces.remove_any() {
         let mut list = VecDeque::new();
         list.push_back((p1,p2));
@@ -103,6 +116,25 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
 
         extend(&mut a, b.0);
         extend(&mut b, a.0);
+
+        // A surveyed length pinned on any of the track's segments replaces
+        // the default length, and is divided evenly among the segments.
+        let mut pinned :Option<((Pt,Pt),f64)> = None;
+        for (p,q) in list.iter() {
+            let (p,q) = (to_vec(*p), to_vec(*q));
+            let pin = model.track_lengths.get(&(p,q)).map(|l| ((p,q),*l))
+                .or_else(|| model.track_lengths.get(&(q,p)).map(|l| ((q,p),*l)));
+            match (pinned, pin) {
+                (Some((seg0,l0)), Some((seg,l))) if (l0 - l).abs() > 1e-6 => {
+                    issues.push(Issue::ConflictingTrackLength(seg0, seg));
+                },
+                (None, Some(pin)) => { pinned = Some(pin); },
+                _ => {},
+            }
+        }
+        if let Some((_,l)) = pinned { length = l; }
+        let segment_length = length / list.len() as f64;
+
         let track_idx = tracks.len();
         tracks.push((to_vec(a.0),to_vec(b.0),length));
         locs.entry(a.0).or_insert(Vec::new()).push(((track_idx, AB::A), to_vec(a.1)));
@@ -112,14 +144,22 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
         //println!("List {:?}", list);
         let mut l = 0.0;
         let mut interval_map = Vec::new();
+        let mut track_gradients = Vec::new();
         for (a,b) in list.iter().cloned() {
-            piece_map.insert((a,b), (tracks.len()-1, l, def_len));
+            piece_map.insert((a,b), (tracks.len()-1, l, segment_length));
+            let (p,q) = (to_vec(a), to_vec(b));
+            let gradient = model.gradients.get(&(p,q)).cloned()
+                .or_else(|| model.gradients.get(&(q,p)).map(|g| -g));
+            if let Some(g) = gradient {
+                track_gradients.push((l, l + segment_length, g));
+            }
             interval_map.push((OrderedFloat(l),glm::vec2(a.0 as f32 ,a.1 as f32)));
-            l += def_len;
+            l += segment_length;
         }
         let last_pt = list[list.len()-1].1;
         interval_map.push((OrderedFloat(l),glm::vec2(last_pt.0 as f32, last_pt.1 as f32)));
         interval_lines.push(interval_map);
+        gradients.push(track_gradients);
         trackobjects.push(Vec::new());
     }
 
@@ -162,17 +202,22 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
                             // TODO this seems unnecessary when we can simply copy the `Function`s.
                             track_objs.push((pos,*id, Function::MainSignal { has_distant: *has_distant },
                                              Some(get_dir_from_side(&pt, *loc))));
-                        }
+                        },
+                        Function::SpeedLimit { speed } => {
+                            track_objs.push((pos,*id, Function::SpeedLimit { speed: *speed },
+                                             Some(get_dir_from_side(&pt, *loc))));
+                        },
                     }
                 }
             } else {
-                println!("WARNING: object trackside position error.");
+                issues.push(Issue::ObjectPlacement(*id));
             }
         } else {
-            println!("WARNING: object outside track network.");
+            issues.push(Issue::ObjectOutsideTrack(*id));
         }
     }
 
+    let track_ends = tracks.iter().map(|(a,b,_)| (*a,*b)).collect::<Vec<_>>();
     let mut tp : Vec<(Option<(Pt,Port)>, Option<(Pt,Port)>, f64)> =
         tracks.into_iter().map(|(_,_,l)| (None,None,l)).collect();
 
@@ -293,26 +338,76 @@ pub fn convert(model :&Model, def_len :f64) -> Result<Topology, ()>{
         if !ok {
             for (end,_p) in conns.as_slice() { settr(*end, Some((p, Port::Err))); }
             locx.insert(p, (NDType::Err, glm::zero()));
+            issues.push(Issue::UnrecognizedNode(p));
         }
 
     }
 
     for (pt,x) in model.node_data.iter() {
-        if let Some((ndtype,tangent)) = locx.get_mut(pt) {
-            *ndtype = *x;
+        if let Some((ndtype,_tangent)) = locx.get_mut(pt) {
+            if matches!(x, NDType::Err) {
+                *ndtype = *x;
+                issues.push(Issue::ErrorNode(*pt));
+            } else if node_data_fits(ndtype, x) {
+                *ndtype = *x;
+            } else if !matches!(ndtype, NDType::Err) {
+                issues.push(Issue::NodeDataMismatch(*pt, *x));
+            }
         }
     }
 
+    let mut converted_tracks = Vec::new();
+    for ((a,b,l),(pt_a,pt_b)) in tp.into_iter().zip(track_ends.into_iter()) {
+        let a = a.ok_or(Issue::UnconnectedTrack(pt_a))?;
+        let b = b.ok_or(Issue::UnconnectedTrack(pt_b))?;
+        converted_tracks.push((l,a,b));
+    }
+
     Ok(
         Topology {
-            tracks: tp.into_iter().map(|(a,b,l)| (l, a.unwrap(), b.unwrap())).collect(),
+            tracks: converted_tracks,
             locations: locx,
             trackobjects: trackobjects,
             interval_lines: interval_lines, 
+            mileages: model.mileages.iter().map(|(r,km)| (*r,*km)).collect(),
+            node_speeds: model.node_speeds.iter().map(|(pt,v)| (*pt,*v)).collect(),
+            gradients: gradients,
+            issues: issues,
         }
     )
 }
 
+/// Node data can only override the node type within the same kind of node,
+/// e.g. changing a model boundary into a buffer stop, or choosing a crossing type.
+fn node_data_fits(shape :&NDType, data :&NDType) -> bool {
+    match (shape, data) {
+        (NDType::OpenEnd, NDType::OpenEnd) | (NDType::OpenEnd, NDType::BufferStop) |
+        (NDType::BufferStop, NDType::OpenEnd) | (NDType::BufferStop, NDType::BufferStop) |
+        (NDType::Cont, NDType::Cont) |
+        (NDType::Crossing(_), NDType::Crossing(_)) => true,
+        (NDType::Sw(a), NDType::Sw(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Find diagonal track pieces crossing each other between grid points,
+/// where no node can be created.
+fn overlapping_segments(model :&Model) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (a,b) in model.linesegs.iter() {
+        if (b.x - a.x).abs() != 1 || (b.y - a.y).abs() != 1 { continue; }
+        let (c,d) = (glm::vec2(a.x, b.y), glm::vec2(b.x, a.y));
+        let crossing = [(c,d),(d,c)].iter().find(|l| model.linesegs.contains(*l)).cloned();
+        if let Some((c,d)) = crossing {
+            // report each pair only once
+            if (a.x,a.y,b.x,b.y) < (c.x,c.y,d.x,d.y) {
+                issues.push(Issue::OverlappingSegments((*a,*b),(c,d)));
+            }
+        }
+    }
+    issues
+}
+
 fn modu(a :i8, b:i8) -> i8 { (a % b + b ) % b }
 
 fn v_angle(v :Vc) -> i8 {
@@ -25,6 +25,12 @@ impl View {
         self.translation = self.translation - delta;
     }
 
+    /// Move the view so that the given point is in the center of a canvas of the given size.
+    pub fn center_on(&mut self, pt :PtC, size :ImVec2) {
+        self.translation = ImVec2 { x:  self.scale as f32 * pt.x - 0.5*size.x,
+                                    y: -self.scale as f32 * pt.y - 0.5*size.y };
+    }
+
     pub fn screen_to_world_ptc(&self, pt :ImVec2) -> PtC {
         let x =  (self.translation.x + pt.x) / self.scale as f32;
         let y = -(self.translation.y + pt.y) / self.scale as f32;
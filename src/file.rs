@@ -1,18 +1,120 @@
 use crate::document::model::Model;
 use std::fs::File;
+use std::io::Read;
+use serde::{Serialize, Deserialize};
 use log::*;
 
+// Documents are stored in an envelope containing a format name and a
+// format version number. Adding fields to the model with `#[serde(default)]`
+// does not require a new version, but any other change to the serialized
+// representation of `Model` (and the types it contains) must bump
+// `FORMAT_VERSION` and add a migration from the previous version in `migrate`.
+//
+// Version history:
+//  0: raw CBOR serialization of `Model` without envelope (junction <= 0.2.10).
+//  1: envelope with format name and version, CBOR or RON.
+
+pub const FORMAT_NAME :&str = "junction";
+pub const FORMAT_VERSION :u32 = 1;
+
+/// Binary (CBOR) or text (RON) file format, chosen by file name extension.
+/// The text form uses RON rather than JSON because the model contains maps
+/// with non-string keys (e.g. objects indexed by location).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format { Binary, Text }
+
+impl Format {
+    pub fn from_filename(filename :&str) -> Format {
+        if filename.to_lowercase().ends_with(".ron") { Format::Text } else { Format::Binary }
+    }
+}
+
+#[derive(Serialize)]
+struct EnvelopeRef<'a> {
+    format :&'a str,
+    version :u32,
+    model :&'a Model,
+}
+
+/// Both fields are missing in files without an envelope.
+#[derive(Deserialize)]
+struct Header {
+    format :Option<String>,
+    version :Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct Envelope<M> {
+    model :M,
+}
+
+fn other_err(e :impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, format!("{}", e))
+}
+
+fn deserialize<'a, T :Deserialize<'a>>(format :Format, bytes :&'a [u8]) -> Result<T, std::io::Error> {
+    match format {
+        Format::Binary => serde_cbor::from_slice(bytes).map_err(other_err),
+        Format::Text => ron::de::from_bytes(bytes).map_err(other_err),
+    }
+}
+
 pub fn load(filename :&str) -> Result<Model, std::io::Error> {
-    let m = serde_cbor::from_reader(File::open(&filename)?)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-    Ok(m)
+    let mut bytes = Vec::new();
+    File::open(&filename)?.read_to_end(&mut bytes)?;
+    from_bytes(Format::from_filename(filename), &bytes)
+}
+
+fn from_bytes(format :Format, bytes :&[u8]) -> Result<Model, std::io::Error> {
+
+    let version = file_version(deserialize::<Header>(format, bytes)
+        .map_err(|e| other_err(format!("Could not read file: {}", e)))?)?;
+    migrate(format, version, bytes)
+}
+
+fn file_version(header :Header) -> Result<u32, std::io::Error> {
+    match (header.format, header.version) {
+        // Files written before the envelope was introduced have no header.
+        (None, None) => Ok(0),
+        (Some(format), Some(version)) => {
+            if format != FORMAT_NAME {
+                return Err(other_err(format!("Unknown file format {:?}", format)));
+            }
+            if version > FORMAT_VERSION {
+                return Err(other_err(format!("File format version {} is newer than supported version {}. \
+                                              Please upgrade {}.", version, FORMAT_VERSION, FORMAT_NAME)));
+            }
+            Ok(version)
+        },
+        _ => Err(other_err("Invalid file header: format name or version is missing")),
+    }
+}
+
+/// Read a model stored in the given format version and bring it up to date
+/// with the current version.
+fn migrate(format :Format, version :u32, bytes :&[u8]) -> Result<Model, std::io::Error> {
+    if version != FORMAT_VERSION { info!("Migrating file from format version {}", version); }
+    match version {
+        0 => Ok(migrate_v0(deserialize(format, bytes)?)),
+        _ => Ok(deserialize::<Envelope<Model>>(format, bytes)?.model),
+    }
 }
 
+/// Version 0 had the same model structure, only without the envelope.
+fn migrate_v0(m :Model) -> Model { m }
+
 pub fn save(filename :&str, m :Model) -> Result<(),std::io::Error> {
     info!("Will save file to file name {:?}", filename);
-    serde_cbor::to_writer(&File::create(filename)?, &m)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-    Ok(())
+    std::fs::write(filename, serialize(Format::from_filename(filename), &m)?)
+}
+
+fn serialize(format :Format, m :&Model) -> Result<Vec<u8>, std::io::Error> {
+    let envelope = EnvelopeRef { format: FORMAT_NAME, version: FORMAT_VERSION, model: m };
+    match format {
+        Format::Binary => serde_cbor::to_vec(&envelope).map_err(other_err),
+        Format::Text => ron::ser::to_string_pretty(&envelope, Default::default())
+            .map(|s| s.into_bytes()).map_err(other_err),
+    }
 }
 
 pub fn save_interactive(m :Model) -> Result<Option<String>,std::io::Error> {
@@ -77,3 +179,64 @@ impl FileInfo {
                                    self.filename.as_ref().map(|x| x.as_str()).unwrap_or("Untitled"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm as glm;
+
+    fn model() -> Model {
+        let mut m = Model::empty();
+        m.linesegs.insert((glm::vec2(0,0), glm::vec2(1,0)));
+        m.gradients.insert((glm::vec2(0,0), glm::vec2(1,0)), 5.0);
+        m
+    }
+
+    fn assert_same(a :&Model, b :&Model) {
+        assert_eq!(a.linesegs, b.linesegs);
+        assert_eq!(a.gradients, b.gradients);
+        assert_eq!(a.vehicles.iter().count(), b.vehicles.iter().count());
+    }
+
+    #[test]
+    pub fn roundtrip_binary() {
+        let m = model();
+        let bytes = serialize(Format::Binary, &m).unwrap();
+        assert_same(&m, &from_bytes(Format::Binary, &bytes).unwrap());
+    }
+
+    #[test]
+    pub fn roundtrip_text() {
+        let m = model();
+        let bytes = serialize(Format::Text, &m).unwrap();
+        assert_same(&m, &from_bytes(Format::Text, &bytes).unwrap());
+    }
+
+    #[test]
+    pub fn load_v0() {
+        let m = model();
+        let bytes = serde_cbor::to_vec(&m).unwrap();
+        assert_same(&m, &from_bytes(Format::Binary, &bytes).unwrap());
+    }
+
+    #[test]
+    pub fn bad_envelope() {
+        #[derive(Serialize)]
+        struct BadVersion { format :&'static str, version :&'static str, model :Model }
+        let bytes = serde_cbor::to_vec(&BadVersion { format: FORMAT_NAME, version: "one", model: model() }).unwrap();
+        assert!(from_bytes(Format::Binary, &bytes).is_err());
+
+        #[derive(Serialize)]
+        struct NoVersion { format :&'static str, model :Model }
+        let bytes = serde_cbor::to_vec(&NoVersion { format: FORMAT_NAME, model: model() }).unwrap();
+        assert!(from_bytes(Format::Binary, &bytes).is_err());
+
+        let envelope = EnvelopeRef { format: "other", version: FORMAT_VERSION, model: &model() };
+        assert!(from_bytes(Format::Binary, &serde_cbor::to_vec(&envelope).unwrap()).is_err());
+
+        let envelope = EnvelopeRef { format: FORMAT_NAME, version: FORMAT_VERSION+1, model: &model() };
+        assert!(from_bytes(Format::Binary, &serde_cbor::to_vec(&envelope).unwrap()).is_err());
+
+        assert!(from_bytes(Format::Binary, &[0xff, 0x00, 0x13]).is_err());
+    }
+}
@@ -13,8 +13,12 @@ use crate::document::*;
 use crate::gui::diagram::DiagramViewAction;
 use crate::gui::infrastructure::draw::highlight_node;
 use crate::document::infview::InfView;
+use crate::document::capacity::Capacity;
 
-pub fn diagram(config :&Config, graphics :&DispatchOutput, draw :&Draw, view :&DiagramViewport) {
+/// Draw the time-distance diagram. With timing settings, sections are
+/// drawn as blocking time stairways instead of reserved and occupied times.
+pub fn diagram(config :&Config, graphics :&DispatchOutput, timing :Option<&TimingSettings>,
+               draw :&Draw, view :&DiagramViewport) {
     let col_res = config.color_u32(RailUIColorName::GraphBlockReserved);
     let col_box = config.color_u32(RailUIColorName::GraphBlockBorder);
     let col_occ = config.color_u32(RailUIColorName::GraphBlockOccupied);
@@ -22,7 +26,9 @@ pub fn diagram(config :&Config, graphics :&DispatchOutput, draw :&Draw, view :&D
     let col_train_front = config.color_u32(RailUIColorName::GraphTrainFront);
     let col_train_rear = config.color_u32(RailUIColorName::GraphTrainRear);
 
-    unsafe {
+    if let Some(timing) = timing {
+        stairways(config, graphics, timing, draw, view);
+    } else { unsafe {
         for block in &graphics.diagram.blocks {
             if block.reserved.0 < block.occupied.0 {
                 ImDrawList_AddRectFilled(draw.draw_list,
@@ -61,7 +67,7 @@ pub fn diagram(config :&Config, graphics :&DispatchOutput, draw :&Draw, view :&D
                 }
             }
         }
-    }
+    }}
 
     for graph in &graphics.diagram.trains {
         for s in &graph.segments {
@@ -88,6 +94,77 @@ pub fn diagram(config :&Config, graphics :&DispatchOutput, draw :&Draw, view :&D
     }
 }
 
+/// Draw blocking time stairways, each band of a section's blocking time in
+/// its own color.
+pub fn stairways(config :&Config, graphics :&DispatchOutput, timing :&TimingSettings,
+                      draw :&Draw, view :&DiagramViewport) {
+    let col_box = config.color_u32(RailUIColorName::GraphBlockBorder);
+    let band_color = |band :BlockingBand| config.color_u32(match band {
+        BlockingBand::RouteSetting => RailUIColorName::GraphBlockRouteSetting,
+        BlockingBand::Approach => RailUIColorName::GraphBlockApproach,
+        BlockingBand::Running => RailUIColorName::GraphBlockRunning,
+        BlockingBand::Clearing => RailUIColorName::GraphBlockClearing,
+        BlockingBand::Release => RailUIColorName::GraphBlockRelease,
+    });
+
+    unsafe {
+        for block in blocking_times(&graphics.diagram, timing) {
+            for (band,(t0,t1)) in block.bands.iter() {
+                if !(t0 < t1) { continue; }
+                ImDrawList_AddRectFilled(draw.draw_list,
+                     to_screen(draw, view, *t0, block.pos.0),
+                     to_screen(draw, view, *t1, block.pos.1),
+                     band_color(*band), 0.0, 0);
+            }
+
+            let t0 = block.bands.first().map(|(_,(t,_))| *t).unwrap_or(0.0);
+            let t1 = block.bands.last().map(|(_,(_,t))| *t).unwrap_or(0.0);
+            ImDrawList_AddRect(draw.draw_list,
+                to_screen(draw, view, t0, block.pos.0),
+                to_screen(draw, view, t1, block.pos.1),
+                col_box, 0.0, 0, 1.0);
+
+            let ra = to_screen(draw,view,t0, block.pos.0) - draw.pos;
+            let rb = to_screen(draw,view,t1, block.pos.1) - draw.pos;
+            if igIsItemHovered(0) {
+                if ra.x <= draw.mouse.x && draw.mouse.x <= rb.x && ra.y <= draw.mouse.y && draw.mouse.y <= rb.y {
+                    igBeginTooltip();
+                    for (band,(t0,t1)) in block.bands.iter() {
+                        widgets::show_text(&format!("{:?} t={:.1} -> t={:.1}", band, t0, t1));
+                    }
+                    igEndTooltip();
+                }
+            }
+        }
+    }
+}
+
+/// Draw the compressed timetable's blocking times as outlines, the 
+/// capacity consumption of each section, and the line section's limits.
+pub fn capacity(config :&Config, capacity :&Capacity, draw :&Draw, view :&DiagramViewport) {
+    let col = config.color_u32(RailUIColorName::GraphBlockCompressed);
+    unsafe {
+        for block in capacity.blocks.iter() {
+            ImDrawList_AddRect(draw.draw_list,
+                to_screen(draw, view, block.blocking.0, block.pos.0),
+                to_screen(draw, view, block.blocking.1, block.pos.1),
+                col, 0.0, 0, 2.0);
+        }
+        for pos in &[capacity.line_section.0, capacity.line_section.1] {
+            ImDrawList_AddLine(draw.draw_list,
+                to_screen(draw, view, view.time.0, *pos),
+                to_screen(draw, view, view.time.1, *pos),
+                col, 1.0);
+        }
+        for section in capacity.sections.iter() {
+            let text = format!("{:.0}%", section.percent);
+            let p = to_screen(draw, view, view.time.0, 0.5*(section.pos.0 + section.pos.1));
+            ImDrawList_AddText(draw.draw_list, ImVec2 { x: p.x, y: draw.pos.y + 2.0 }, col,
+                               text.as_ptr() as _, text.as_ptr().offset(text.len() as isize) as _);
+        }
+    }
+}
+
 struct Polyline {
     pub path :Vec<ImVec2>,
 }
@@ -214,7 +291,15 @@ pub fn command_icons(config :&Config,
                     (Command::Train(v,_),_) => {
                         let v = analysis.model().vehicles.get(*v).map(|v| v.name.as_str())
                             .unwrap_or("Unknown vehicle");
-                        widgets::show_text(&format!("{} entering t={:.1}", v, cmd_t));
+                        let entry = dispatch.trains.get(cmd_id);
+                        let name = entry.and_then(|e| e.name.as_ref())
+                            .map(|n| format!("{} ({})", n, v)).unwrap_or(v.to_string());
+                        let speed = match entry.map(|e| e.entry_speed).unwrap_or_default() {
+                            model::EntrySpeed::Standstill => String::new(),
+                            model::EntrySpeed::LineSpeed => format!(" at line speed"),
+                            model::EntrySpeed::Speed(s) => format!(" at {:.0} km/h", s),
+                        };
+                        widgets::show_text(&format!("{} entering t={:.1}{}", name, cmd_t, speed));
                     },
                 }
                 igEndTooltip();
@@ -11,13 +11,52 @@ use crate::app::*;
 use crate::document::*;
 use crate::gui::widgets::Draw;
 use crate::document::infview::InfView;
+use crate::document::capacity;
+use log::*;
+use matches::matches;
 
 mod draw;
 
-#[derive(Copy,Clone)]
+#[derive(Clone)]
 pub enum DiagramViewAction {
     DeleteCommand { id :usize },
     MoveCommand { idx :usize, id :usize, t :f64 },
+    TrainEntry { id :usize, entry :model::TrainEntry },
+}
+
+/// Returns the new train properties if edited.
+fn edit_train_entry(entry :Option<&model::TrainEntry>) -> Option<model::TrainEntry> {
+    let mut entry = entry.cloned().unwrap_or_default();
+    let mut edited = false;
+    unsafe {
+        if let Some(name) = widgets::edit_text(const_cstr!("Train name").as_ptr(), 
+                                               entry.name.clone().unwrap_or_default()) {
+            entry.name = if name.is_empty() { None } else { Some(name) };
+            edited = true;
+        }
+        let options = [(const_cstr!("Standstill"), model::EntrySpeed::Standstill),
+                       (const_cstr!("Line speed"), model::EntrySpeed::LineSpeed),
+                       (const_cstr!("Speed"), model::EntrySpeed::Speed(0.0))];
+        for (label, speed) in options.iter() {
+            let selected = std::mem::discriminant(&entry.entry_speed) == std::mem::discriminant(speed);
+            if igRadioButtonBool(label.as_ptr(), selected) && !selected {
+                entry.entry_speed = *speed;
+                edited = true;
+            }
+            if igIsItemHovered(0) && *speed != model::EntrySpeed::Standstill {
+                igBeginTooltip();
+                widgets::show_text("The train passes the model boundary at this speed at its entry time.\n\
+                                    Trains starting inside the model start from standstill.");
+                igEndTooltip();
+            }
+        }
+        if let model::EntrySpeed::Speed(speed) = &mut entry.entry_speed {
+            igInputDouble(const_cstr!("Entry speed (km/h)").as_ptr(), speed, 5.0, 20.0,
+                          const_cstr!("%.0f").as_ptr(), 0 as _);
+            if igIsItemEdited() { *speed = speed.max(0.0); edited = true; }
+        }
+    }
+    if edited { Some(entry) } else { None }
 }
 
 pub fn default_viewport(graph :&DispatchOutput) -> DiagramViewport {
@@ -34,7 +73,7 @@ pub fn diagram_view(config :&Config, inf_canvas :Option<&Draw>, inf_view :&InfVi
                     analysis :&Analysis, dv :&mut ManualDispatchView, graph :&DispatchOutput) -> Option<DiagramViewAction> {
     let mut action = None;
     unsafe {
-        diagram_toolbar(dv, graph);
+        diagram_toolbar(analysis, dv, graph);
         let size = igGetContentRegionAvail_nonUDT2().into();
         let draw = widgets::canvas(size,
                     config.color_u32(RailUIColorName::GraphBackground),
@@ -56,7 +95,13 @@ pub fn diagram_view(config :&Config, inf_canvas :Option<&Draw>, inf_view :&InfVi
                                              graph.time_interval.1 as f64);
 
         // Need to get a DispatchOutput from analysis.
-        draw::diagram(config, graph, &draw, dv.viewport.as_ref().unwrap());
+        let timing = if dv.show_blocking_times { Some(&analysis.model().settings.timing) } else { None };
+        draw::diagram(config, graph, timing, &draw, dv.viewport.as_ref().unwrap());
+        if dv.show_capacity {
+            let line_section = dv.line_section.unwrap_or(graph.line());
+            let capacity = graph.capacity(&analysis.model().settings.timing, line_section);
+            draw::capacity(config, &capacity, &draw, dv.viewport.as_ref().unwrap());
+        }
         action = draw::command_icons(config, inf_canvas, inf_view, analysis, graph, &draw, dv).or(action);
         draw::time_slider(config, &draw, dv.viewport.as_ref().unwrap(), dv.time);
 
@@ -76,6 +121,14 @@ pub fn diagram_view(config :&Config, inf_canvas :Option<&Draw>, inf_view :&InfVi
 
         if igBeginPopup(const_cstr!("cmded").as_ptr(), 0 as _) {
             if let Some(selection) = dv.selected_command {
+                let is_train = graph.dispatch.commands.iter()
+                    .any(|(id,(_,c))| *id == selection && matches!(c, model::Command::Train(_,_)));
+                if is_train {
+                    if let Some(entry) = edit_train_entry(graph.dispatch.trains.get(&selection)) {
+                        action = Some(DiagramViewAction::TrainEntry { id: selection, entry });
+                    }
+                    widgets::sep();
+                }
                 if igSelectable(const_cstr!("Delete").as_ptr(), false, 0 as _, ImVec2::zero()) {
                     action = Some(DiagramViewAction::DeleteCommand { id: selection });
                 }
@@ -121,7 +174,37 @@ fn scroll(draw :&Draw, viewport :&mut DiagramViewport) {
 }
 
 
-fn diagram_toolbar(dv :&mut ManualDispatchView, graph :&DispatchOutput) {
+fn export_capacity(analysis :&Analysis, capacity :&capacity::Capacity) -> Result<(), std::io::Error> {
+    let dgraph = match &analysis.data().dgraph { Some((_,dgraph)) => dgraph, None => return Ok(()) };
+    if let Some(filename) = tinyfiledialogs::save_file_dialog("Export capacity consumption", "") {
+        std::fs::write(filename, capacity::to_csv(dgraph, capacity))?;
+    }
+    Ok(())
+}
+
+/// Select the mileage interval of the line section used for capacity consumption.
+fn edit_line_section(dv :&mut ManualDispatchView, graph :&DispatchOutput) {
+    unsafe {
+        let (mut from, mut to) = dv.line_section.unwrap_or(graph.line());
+        let mut edited = false;
+        igPushItemWidth(100.0);
+        igInputDouble(const_cstr!("From km").as_ptr(), &mut from, 100.0, 1000.0,
+                      const_cstr!("%.0f").as_ptr(), 0 as _);
+        edited |= igIsItemEdited();
+        igSameLine(0.0,-1.0);
+        igInputDouble(const_cstr!("To km").as_ptr(), &mut to, 100.0, 1000.0,
+                      const_cstr!("%.0f").as_ptr(), 0 as _);
+        edited |= igIsItemEdited();
+        igPopItemWidth();
+        if edited { dv.line_section = Some((from.min(to), from.max(to))); }
+        igSameLine(0.0,-1.0);
+        if igButton(const_cstr!("Whole line").as_ptr(), ImVec2::zero()) {
+            dv.line_section = None;
+        }
+    }
+}
+
+fn diagram_toolbar(analysis :&Analysis, dv :&mut ManualDispatchView, graph :&DispatchOutput) {
     unsafe {
     let label = if dv.play { const_cstr!("\u{f04c}") }
                 else { const_cstr!("\u{f04b}") };
@@ -132,5 +215,23 @@ fn diagram_toolbar(dv :&mut ManualDispatchView, graph :&DispatchOutput) {
     if igButton(const_cstr!("\u{f0b2}").as_ptr(), ImVec2::zero()) {
         dv.viewport = Some(default_viewport(graph));
     }
+    igSameLine(0.0,-1.0);
+    igCheckbox(const_cstr!("Blocking times").as_ptr(), &mut dv.show_blocking_times);
+    igSameLine(0.0,-1.0);
+    igCheckbox(const_cstr!("Capacity").as_ptr(), &mut dv.show_capacity);
+    if dv.show_capacity {
+        igSameLine(0.0,-1.0);
+        edit_line_section(dv, graph);
+        let line_section = dv.line_section.unwrap_or(graph.line());
+        let capacity = graph.capacity(&analysis.model().settings.timing, line_section);
+        igSameLine(0.0,-1.0);
+        widgets::show_text(&format!("{:.0}%", capacity.percent));
+        igSameLine(0.0,-1.0);
+        if igButton(const_cstr!("Export...").as_ptr(), ImVec2::zero()) {
+            if let Err(e) = export_capacity(analysis, &capacity) {
+                error!("Error exporting capacity consumption: {}", e);
+            }
+        }
+    }
     }
 }
@@ -31,7 +31,9 @@ pub fn dispatch_view(config :&Config, inf_canvas :Option<&Draw>, inf_view :&InfV
                     analysis.edit_model(|m| {
                         match action {
                             DiagramViewAction::DeleteCommand { id } => {
-                                m.dispatches.get_mut(manual.dispatch_idx)?.commands.retain(|(x,_)| *x != id);
+                                let dispatch = m.dispatches.get_mut(manual.dispatch_idx)?;
+                                dispatch.commands.retain(|(x,_)| *x != id);
+                                dispatch.trains.remove(&id);
                             },
                             DiagramViewAction::MoveCommand { idx, id, t } => {
                                 let commands = &mut m.dispatches.get_mut(manual.dispatch_idx)?.commands;
@@ -39,7 +41,11 @@ pub fn dispatch_view(config :&Config, inf_canvas :Option<&Draw>, inf_view :&InfV
                                     if *c_id == id { *c_t = t; }
                                 }
                                 commands.sort_by_key(|(_,(t,_))| OrderedFloat(*t));
-                            }
+                            },
+                            DiagramViewAction::TrainEntry { id, entry } => {
+                                m.dispatches.get_mut(manual.dispatch_idx)?.trains.insert(id, entry);
+                                return Some(model::EditClass::TrainEntry(manual.dispatch_idx, id));
+                            },
                         };
                         None
                     });
@@ -56,6 +62,18 @@ pub fn dispatch_view(config :&Config, inf_canvas :Option<&Draw>, inf_view :&InfV
 
             if let Some(manual) = &mut auto.dispatch {
                 if let Some(Some((_gen,dispatches))) = analysis.data().plandispatches.get(auto.plan_idx) {
+                    // Follow the selected dispatch if it has moved after re-planning.
+                    if let Some(key) = auto.dispatch_key {
+                        use crate::document::plan::dispatch_key;
+                        let same = dispatches.get(manual.dispatch_idx)
+                            .map(|d| dispatch_key(&d.dispatch.commands) == key).unwrap_or(false);
+                        if !same {
+                            if let Some(idx) = dispatches.iter()
+                                    .position(|d| dispatch_key(&d.dispatch.commands) == key) {
+                                manual.dispatch_idx = idx;
+                            }
+                        }
+                    }
                     if let Some(graph) = dispatches.get(manual.dispatch_idx) {
                         diagram_view(config, inf_canvas, inf_view, analysis, manual, graph);
                     } else {
@@ -191,6 +209,7 @@ pub fn dispatch_select_bar(config :&Config, dispatch_view :&Option<DispatchView>
                     retval = Some(Some(DispatchView::Auto(AutoDispatchView {
                         plan_idx: *id,
                         dispatch: None,
+                        dispatch_key: None,
                         action: PlanViewAction::None,
                     })));
                 }
@@ -266,6 +285,7 @@ pub fn dispatch_select_bar(config :&Config, dispatch_view :&Option<DispatchView>
             retval = Some(Some(DispatchView::Auto(AutoDispatchView {
                 plan_idx: id,
                 dispatch: None,
+                dispatch_key: None,
                 action: PlanViewAction::None,
             })));
         }
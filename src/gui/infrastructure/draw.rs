@@ -191,11 +191,12 @@ pub fn route(config :&Config, analysis :&Analysis, inf_view :&InfView, draw :&Dr
     unsafe {
         let il = &analysis.data().interlocking.as_ref()?.1;
         let dgraph = &analysis.data().dgraph.as_ref()?.1;
-        let RouteInfo { route, path, ..} = &il.routes[route_idx];
+        let RouteInfo { route, path, flank, ..} = &il.routes[route_idx];
         let color_path = config.color_u32(RailUIColorName::CanvasRoutePath);
         let color_section = config.color_u32(RailUIColorName::CanvasRouteSection);
+        let color_overlap = config.color_u32(RailUIColorName::CanvasRouteOverlap);
 
-        for sec in route.resources.sections.iter() {
+        let draw_section = |sec :&rolling_inf::ObjectId, color :u32| {
             if let Some(edges) = dgraph.tvd_edges.get(sec) {
                 for (a,b) in edges.iter() {
                     if let Some((v,_)) = util::get_symm(&dgraph.edge_lines, (*a,*b)) {
@@ -203,11 +204,21 @@ pub fn route(config :&Config, analysis :&Analysis, inf_view :&InfView, draw :&Dr
                             ImDrawList_AddLine(draw.draw_list,
                                                draw.pos + inf_view.view.world_ptc_to_screen(*pt_a),
                                                draw.pos + inf_view.view.world_ptc_to_screen(*pt_b),
-                                               color_section, 2.0*7.0);
+                                               color, 2.0*7.0);
                         }
                     }
                 }
             }
+        };
+
+        for overlap in route.overlaps.iter() {
+            for sec in overlap.sections.iter() {
+                draw_section(sec, color_overlap);
+            }
+        }
+
+        for sec in route.resources.sections.iter() {
+            draw_section(sec, color_section);
         }
 
         for (a,b) in path {
@@ -222,6 +233,18 @@ pub fn route(config :&Config, analysis :&Analysis, inf_view :&InfView, draw :&Dr
         }
         // TODO highlight end signal/boundary
 
+        let color_flank = config.color_u32(RailUIColorName::CanvasRouteFlank);
+        let flank_pts = flank.switches.iter()
+            .filter_map(|(sw,_)| dgraph.switch_ids.get_by_left(sw)
+                        .map(|pt| glm::vec2(pt.x as f32, pt.y as f32)))
+            .chain(flank.signals.iter()
+                   .filter_map(|s| dgraph.object_ids.get_by_left(s).map(|pta| unround_coord(*pta))));
+        for pt in flank_pts {
+            ImDrawList_AddCircleFilled(draw.draw_list, 
+                                       draw.pos + inf_view.view.world_ptc_to_screen(pt),
+                                       8.0, color_flank, 8);
+        }
+
         Some(())
     }
 }
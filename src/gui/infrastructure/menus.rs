@@ -3,6 +3,7 @@ use matches::matches;
 use backend_glfw::imgui::*;
 use rolling::input::staticinfrastructure as rolling_inf;
 use std::ffi::CString;
+use nalgebra_glm as glm;
 
 use crate::app::App;
 use crate::document::*;
@@ -35,11 +36,7 @@ pub fn node_editor(analysis :&mut Analysis, pt :Pt) -> Option<()> {
         },
         NDType::Sw(side) => {
             widgets::show_text(&format!("Switch ({:?})", side));
-
-            // TODO 
-            let mut speed = 60.0;
-            igInputFloat(const_cstr!("Deviating speed restr.").as_ptr(), &mut speed, 1.0, 10.0,
-                         const_cstr!("%.1f").as_ptr(), 0 as _);
+            node_speed(analysis, pt, const_cstr!("Deviating speed (km/h)").as_ptr());
         },
         NDType::Crossing(type_) => {
             widgets::show_text(&format!("Crossing ({:?})", type_));
@@ -55,10 +52,7 @@ pub fn node_editor(analysis :&mut Analysis, pt :Pt) -> Option<()> {
                 });
             }
 
-            // TODO 
-            let mut speed = 60.0;
-            igInputFloat(const_cstr!("Deviating speed restr.").as_ptr(), &mut speed, 1.0, 10.0,
-                         const_cstr!("%.1f").as_ptr(), 0 as _);
+            node_speed(analysis, pt, const_cstr!("Crossing speed (km/h)").as_ptr());
         }
         _ => {},
     }
@@ -67,10 +61,117 @@ pub fn node_editor(analysis :&mut Analysis, pt :Pt) -> Option<()> {
 }
 
 
+/// Speed restriction on a switch or crossing. Zero means no restriction.
+fn node_speed(analysis :&mut Analysis, pt :Pt, label :*const i8) {
+    let mut speed = analysis.model().node_speeds.get(&pt).cloned().unwrap_or(0) as i32;
+    unsafe {
+        igInputInt(label, &mut speed, 5, 10, 0 as _);
+        if igIsItemEdited() {
+            analysis.edit_model(|m| {
+                if speed > 0 { m.node_speeds.insert(pt, speed as u32); }
+                else { m.node_speeds.remove(&pt); }
+                Some(EditClass::NodeSpeed(pt))
+            });
+        }
+        if igIsItemHovered(0) {
+            igBeginTooltip();
+            widgets::show_text("Zero means no speed restriction.");
+            igEndTooltip();
+        }
+    }
+}
+
+/// Gradient on a line segment, rising towards the segment's second point.
+pub fn gradient_editor(analysis :&mut Analysis, a :Pt, b :Pt) {
+    let mut gradient = analysis.model().gradients.get(&(a,b)).cloned().unwrap_or(0.0);
+    unsafe {
+        igInputDouble(const_cstr!("Gradient (\u{2030})").as_ptr(), &mut gradient, 1.0, 5.0,
+                      const_cstr!("%.1f").as_ptr(), 0 as _);
+        if igIsItemEdited() {
+            analysis.edit_model(|m| {
+                if gradient != 0.0 { m.gradients.insert((a,b), gradient); }
+                else { m.gradients.remove(&(a,b)); }
+                Some(EditClass::Gradient(a,b))
+            });
+        }
+        if igIsItemHovered(0) {
+            igBeginTooltip();
+            widgets::show_text("Positive when rising from left to right.");
+            igEndTooltip();
+        }
+    }
+}
+
+/// Pin a surveyed track length on a line segment, or an absolute mileage
+/// on a node or object.
+pub fn pin_editor(analysis :&mut Analysis, thing :Ref) -> Option<()> {
+    let (label, pinned) = match thing {
+        Ref::LineSeg(a,b) => (const_cstr!("Fixed length (m)"), analysis.model().track_lengths.get(&(a,b)).cloned()),
+        _ => (const_cstr!("Fixed mileage (m)"), analysis.model().mileages.get(&thing).cloned()),
+    };
+
+    // Start from the current computed value when pinning.
+    let current = || -> Option<f64> {
+        match thing {
+            Ref::LineSeg(a,b) => {
+                let topo = &analysis.data().topology.as_ref()?.1;
+                let (a,b) = (glm::vec2(a.x as f32, a.y as f32), glm::vec2(b.x as f32, b.y as f32));
+                let idx = topo.interval_lines.iter().position(|line| line.windows(2)
+                    .any(|w| (w[0].1 == a && w[1].1 == b) || (w[0].1 == b && w[1].1 == a)))?;
+                Some(topo.tracks[idx].0)
+            },
+            Ref::Node(pt) => {
+                let dgraph = &analysis.data().dgraph.as_ref()?.1;
+                dgraph.mileage.get(dgraph.node_ids.get_by_right(&pt)?).cloned()
+            },
+            Ref::Object(pta) => {
+                let dgraph = &analysis.data().dgraph.as_ref()?.1;
+                dgraph.mileage.get(dgraph.detector_ids.get_by_right(&pta)?).cloned()
+            },
+        }
+    };
+
+    let mut fixed = pinned.is_some();
+    let mut value = pinned.unwrap_or(0.0);
+    let mut new_value = None;
+    unsafe {
+        igPushIDInt(1);
+        igCheckbox(const_cstr!("##fixed").as_ptr(), &mut fixed);
+        if igIsItemEdited() {
+            new_value = Some(if fixed { Some(current().unwrap_or(0.0)) } else { None });
+        }
+        igSameLine(0.0,-1.0);
+        if fixed {
+            igInputDouble(label.as_ptr(), &mut value, 1.0, 100.0, const_cstr!("%.1f").as_ptr(), 0 as _);
+            if igIsItemEdited() { new_value = Some(Some(value)); }
+        } else {
+            widgets::show_text(label.as_str());
+        }
+        igPopID();
+    }
+
+    if let Some(new_value) = new_value {
+        analysis.edit_model(|m| {
+            match (thing, new_value) {
+                (Ref::LineSeg(a,b), Some(l)) => { m.track_lengths.insert((a,b), l.max(0.0)); },
+                (Ref::LineSeg(a,b), None) => { m.track_lengths.remove(&(a,b)); },
+                (_, Some(km)) => { m.mileages.insert(thing, km); },
+                (_, None) => { m.mileages.remove(&thing); },
+            }
+            Some(match thing {
+                Ref::LineSeg(a,b) => EditClass::TrackLength(a,b),
+                _ => EditClass::Mileage(thing),
+            })
+        });
+    }
+    Some(())
+}
+
 pub fn object_menu(analysis :&mut Analysis, pta :PtA) -> Option<()> {
     let obj = analysis.model().objects.get(&pta)?;
 
     let mut set_distant = None;
+    let mut set_speed = None;
     for f in obj.functions.iter() {
         match f {
             Function::Detector => { widgets::show_text("Detector"); },
@@ -83,7 +184,17 @@ pub fn object_menu(analysis :&mut Analysis, pta :PtA) -> Option<()> {
                         set_distant = Some(has_distant);
                     }
                 }
-            }
+            },
+            Function::SpeedLimit { speed } => {
+                widgets::show_text("Speed limit");
+                let mut speed = *speed as i32;
+                unsafe {
+                    igInputInt(const_cstr!("Speed (km/h)").as_ptr(), &mut speed, 5, 10, 0 as _);
+                    if igIsItemEdited() {
+                        set_speed = Some(speed.max(1) as u32);
+                    }
+                }
+            },
         }
     }
     if let Some(d) = set_distant {
@@ -92,6 +203,12 @@ pub fn object_menu(analysis :&mut Analysis, pta :PtA) -> Option<()> {
             None
         });
     }
+    if let Some(speed) = set_speed {
+        analysis.edit_model(|new| {
+            new.objects.get_mut(&pta).unwrap().functions = vec![Function::SpeedLimit { speed }];
+            Some(EditClass::ObjectSpeed(pta))
+        });
+    }
     Some(())
 }
 
@@ -207,7 +324,7 @@ pub fn add_plan_visit(analysis :&mut Analysis,
 
         if let Some(plan_idx) = set_plan { 
             *dispatch_view = Some(DispatchView::Auto(AutoDispatchView { 
-                plan_idx, dispatch: None, action: PlanViewAction::None, }));
+                plan_idx, dispatch: None, dispatch_key: None, action: PlanViewAction::None, }));
         }
     }
 }
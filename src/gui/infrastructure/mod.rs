@@ -464,6 +464,15 @@ fn object_select(inf_view :&mut InfView) {
                         }
                         ));
             } 
+            if igSelectable(const_cstr!("Speed limit").as_ptr(), false, 0 as _, ImVec2::zero()) {
+                inf_view.action = Action::InsertObject(Some( 
+                        Object {
+                            loc: glm::vec2(0.0, 0.0),
+                            tangent: glm::vec2(1,0),
+                            functions: vec![Function::SpeedLimit { speed: 80 }],
+                        }
+                        ));
+            } 
 
             igEndPopup();
         }
@@ -557,6 +566,13 @@ fn context_menu_single(analysis :&mut Analysis,
         widgets::sep();
     }
 
+    // Surveyed track lengths and mileages
+    menus::pin_editor(analysis, thing);
+    if let Ref::LineSeg(a,b) = thing {
+        menus::gradient_editor(analysis, a, b);
+    }
+    widgets::sep();
+
     // Manual dispatch from boundaries and signals
     let action = menus::route_selector(analysis, dispatch_view, thing, preview_route);
     if let Some(routespec) = action {
@@ -24,6 +24,26 @@ pub fn load(app :&mut App) {
     };
 }
 
+/// Write the route table of the current document to a CSV file, or to a
+/// Markdown file if the chosen file name does not end with `.csv`.
+pub fn export_route_table(app :&App) -> Result<(), std::io::Error> {
+    let data = app.document.analysis.data();
+    let (dgraph, il) = match (&data.dgraph, &data.interlocking) {
+        (Some((_,dgraph)), Some((_,il))) => (dgraph, il),
+        _ => return Ok(()),
+    };
+    if let Some(filename) = tinyfiledialogs::save_file_dialog("Export route table", "") {
+        let rows = crate::document::routetable::rows(dgraph, il);
+        let contents = if filename.to_lowercase().ends_with(".csv") {
+            crate::document::routetable::to_csv(&rows)
+        } else {
+            crate::document::routetable::to_markdown(&rows)
+        };
+        std::fs::write(filename, contents)?;
+    }
+    Ok(())
+}
+
 pub fn main_menu(app :&mut App) {
     unsafe {
         if igBeginMenuBar() {
@@ -85,6 +105,14 @@ pub fn main_menu(app :&mut App) {
                     // TODO 
                 }
 
+                let has_interlocking = app.document.analysis.data().interlocking.is_some();
+                if igMenuItemBool(const_cstr!("Export route table...").as_ptr(), 
+                                  std::ptr::null(), false, has_interlocking) {
+                    if let Err(e) = export_route_table(app) {
+                        error!("Error exporting route table: {}", e);
+                    }
+                }
+
                 widgets::sep();
                 if igMenuItemBool(const_cstr!("Quit").as_ptr(), 
                                   std::ptr::null(), false, true) {
@@ -98,6 +126,10 @@ pub fn main_menu(app :&mut App) {
                                   std::ptr::null(), app.windows.vehicles, true) {
                     app.windows.vehicles = !app.windows.vehicles;
                 }
+                if igMenuItemBool(const_cstr!("Document settings").as_ptr(), 
+                                  std::ptr::null(), app.windows.settings, true) {
+                    app.windows.settings = !app.windows.settings;
+                }
                 if igMenuItemBool(const_cstr!("Signal designer").as_ptr(), 
                                   std::ptr::null(), app.windows.synthesis_window.is_some(), true) {
                     if app.windows.synthesis_window.is_none() {
@@ -108,6 +140,15 @@ pub fn main_menu(app :&mut App) {
 
                     }
                 }
+                if igMenuItemBool(const_cstr!("Plan templates").as_ptr(), 
+                                  std::ptr::null(), app.windows.templates_window.is_some(), true) {
+                    if app.windows.templates_window.is_none() {
+                        app.windows.templates_window = Some(gui::windows::templates::TemplatesWindow::new(
+                                app.document.analysis.model()));
+                    } else {
+                        app.windows.templates_window = None;
+                    }
+                }
                 if igMenuItemBool(const_cstr!("Delete all objects").as_ptr(), std::ptr::null(), false, true) {
                     app.document.analysis.edit_model(|m| {
                         m.objects.clear();
@@ -121,6 +162,10 @@ pub fn main_menu(app :&mut App) {
                                   std::ptr::null(), app.windows.log, true) {
                     app.windows.log = !app.windows.log;
                 }
+                if igMenuItemBool(const_cstr!("Issues").as_ptr(), 
+                                  std::ptr::null(), app.windows.issues, true) {
+                    app.windows.issues = !app.windows.issues;
+                }
                 igEndMenu();
             }
             if igBeginMenu(const_cstr!("Tools").as_ptr(), true) {
@@ -128,6 +173,10 @@ pub fn main_menu(app :&mut App) {
                                   std::ptr::null(), app.windows.debug, true) {
                     app.windows.debug = !app.windows.debug;
                 }
+                if igMenuItemBool(const_cstr!("Route conflicts").as_ptr(), 
+                                  std::ptr::null(), app.windows.conflicts, true) {
+                    app.windows.conflicts = !app.windows.conflicts;
+                }
                 if igMenuItemBool(const_cstr!("Configure colors").as_ptr(), 
                                   std::ptr::null(), app.windows.config, true) {
                     app.windows.config = !app.windows.config;
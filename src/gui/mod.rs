@@ -70,12 +70,21 @@ pub fn main(app :&mut App) -> bool {
     windows::logview::view_log(&mut app.windows.log, &app.log);
     app.windows.debug = windows::debug::debug_window(app.windows.debug, &app, 
                                                      inf_canvas.as_ref(), &app.document.inf_view );
-    windows::vehicles::edit_vehicles_window(&mut app.windows.vehicles, &mut app.document);
+    windows::vehicles::edit_vehicles_window(&mut app.windows.vehicles, &mut app.document,
+                                            &mut app.windows.vehicle_library);
+    windows::settings::edit_settings_window(&mut app.windows.settings, &mut app.document);
+    windows::issues::issues_window(&mut app.windows.issues, &app.config, &app.document.analysis,
+                                   inf_canvas.as_ref(), &mut app.document.inf_view);
+    windows::conflicts::conflicts_window(&mut app.windows.conflicts, &app.config, &app.document.analysis,
+                                         inf_canvas.as_ref(), &app.document.inf_view);
     windows::config::edit_config_window(&mut app.windows.config, &mut app.config);
 
     app.windows.import_window.draw(&mut app.document.analysis);
     if let Some(win) = &mut app.windows.synthesis_window { if !win.draw(&mut app.document.analysis) {
         app.windows.synthesis_window = None; }}
+    if let Some(win) = &mut app.windows.templates_window { 
+        if !win.draw(&mut app.document.analysis, &app.document.inf_view, &mut app.document.dispatch_view) {
+            app.windows.templates_window = None; }}
 
     // Quit dialog
     let really_quit = if app.windows.quit {
@@ -20,6 +20,7 @@ enum Action {
     VisitMoveBefore { source: VisitKey, target :VisitKey },
     VisitMoveToEnd { source: VisitKey, target: usize }, // Train id
     OrderDeleteAt { key :VisitKey },
+    VisitDwell { key :VisitKey, dwell :Option<f64> },
     TrainVehicle { train: usize, vehicle: usize },
     NewTrain,
     RemoveTrain { train: usize },
@@ -51,7 +52,25 @@ pub fn edit_plan(config :&Config,
         }
 
         igSameLine(0.0,-1.0);
-        plan_dispatches(config, analysis, auto_dispatch);
+        if igButton(const_cstr!("\u{f013} Search").as_ptr(), ImVec2::zero()) {
+            igOpenPopup(const_cstr!("plncfg").as_ptr());
+        }
+        if igBeginPopup(const_cstr!("plncfg").as_ptr(), 0 as _) {
+            edit_plan_config(analysis, plan_idx);
+            igEndPopup();
+        }
+
+        igSameLine(0.0,-1.0);
+        match plan_dispatches(config, analysis, auto_dispatch) {
+            Some(PlanDispatchAction::CopyAsManual(dispatch_idx)) => {
+                new_dispatchview = copy_as_manual(analysis, plan_idx, dispatch_idx)
+                    .map(|id| Some(DispatchView::Manual(ManualDispatchView::new(id))));
+            },
+            Some(PlanDispatchAction::CycleTime(dispatch_idx)) => {
+                analysis.request_cycle_time(plan_idx, dispatch_idx);
+            },
+            None => {},
+        }
 
         widgets::sep();
 
@@ -285,6 +304,15 @@ pub fn edit_plan(config :&Config,
                 None
             });
         },
+        Some(Action::VisitDwell { key, dwell }) => {
+            analysis.edit_model(|m| {
+                let plan = m.plans.get_mut(plan_idx)?;
+                let (_,train) = plan.trains.get_mut(key.train)?;
+                let visit = train.get_mut(key.visit)?;
+                visit.dwell = dwell;
+                Some(EditClass::VisitDwell(plan_idx, key.train, key.visit))
+            });
+        },
         Some(Action::VisitDelete { key }) => {
             analysis.edit_model(|m| {
                 let plan = m.plans.get_mut(plan_idx)?;
@@ -336,22 +364,154 @@ pub fn planning_icon(config :&Config, analysis :&Analysis, generation :usize, di
     }
 }
 
-fn plan_dispatches(config :&Config, analysis :&Analysis, adv :&mut AutoDispatchView)  {
+/// Planner search limits for a plan.
+fn edit_plan_config(analysis :&mut Analysis, plan_idx :usize) -> Option<()> {
+    let mut cfg = analysis.model().plans.get(plan_idx)?.config.clone();
+    let mut modified = false;
+    unsafe {
+        let mut n_before = cfg.n_before as i32;
+        igInputInt(const_cstr!("States before").as_ptr(), &mut n_before, 1, 5, 0 as _);
+        if igIsItemEdited() { cfg.n_before = n_before.max(0) as usize; modified = true; }
+
+        let mut n_after = cfg.n_after as i32;
+        igInputInt(const_cstr!("States after").as_ptr(), &mut n_after, 1, 5, 0 as _);
+        if igIsItemEdited() { cfg.n_after = n_after.max(0) as usize; modified = true; }
+
+        let mut has_exact = cfg.exact_n.is_some();
+        if igCheckbox(const_cstr!("Exact number of states").as_ptr(), &mut has_exact) {
+            cfg.exact_n = if has_exact { Some(cfg.n_before + cfg.n_after) } else { None };
+            modified = true;
+        }
+        if let Some(n) = cfg.exact_n {
+            let mut n = n as i32;
+            igInputInt(const_cstr!("States").as_ptr(), &mut n, 1, 5, 0 as _);
+            if igIsItemEdited() { cfg.exact_n = Some(n.max(0) as usize); modified = true; }
+        }
+
+        if igCheckbox(const_cstr!("Optimize signals").as_ptr(), &mut cfg.optimize_signals) {
+            modified = true;
+        }
+
+        let objective_name = CString::new(cfg.objective.name()).unwrap();
+        if igBeginCombo(const_cstr!("Sort by").as_ptr(), objective_name.as_ptr(), 0) {
+            for objective in PlanObjective::all() {
+                let name = CString::new(objective.name()).unwrap();
+                if igSelectable(name.as_ptr(), cfg.objective == *objective, 0 as _, ImVec2::zero()) {
+                    cfg.objective = *objective;
+                    modified = true;
+                }
+            }
+            igEndCombo();
+        }
+
+        let mut periodic = cfg.cycle_time.is_some();
+        if igCheckbox(const_cstr!("Periodic").as_ptr(), &mut periodic) {
+            cfg.cycle_time = if periodic { Some(600.0) } else { None };
+            modified = true;
+        }
+        if let Some(cycle) = cfg.cycle_time {
+            let mut cycle = cycle;
+            igInputDouble(const_cstr!("Cycle time (s)").as_ptr(), &mut cycle, 10.0, 60.0,
+                          const_cstr!("%.0f").as_ptr(), 0 as _);
+            if igIsItemEdited() { cfg.cycle_time = Some(cycle.max(0.0)); modified = true; }
+        }
+
+        widgets::sep();
+
+        let mut has_max = cfg.max_dispatches.is_some();
+        if igCheckbox(const_cstr!("Limit number of dispatches").as_ptr(), &mut has_max) {
+            cfg.max_dispatches = if has_max { Some(10) } else { None };
+            modified = true;
+        }
+        if let Some(max) = cfg.max_dispatches {
+            let mut max = max as i32;
+            igInputInt(const_cstr!("Max. dispatches").as_ptr(), &mut max, 1, 5, 0 as _);
+            if igIsItemEdited() { cfg.max_dispatches = Some(max.max(1) as usize); modified = true; }
+        }
+
+        let mut has_timeout = cfg.timeout.is_some();
+        if igCheckbox(const_cstr!("Search timeout").as_ptr(), &mut has_timeout) {
+            cfg.timeout = if has_timeout { Some(10.0) } else { None };
+            modified = true;
+        }
+        if let Some(timeout) = cfg.timeout {
+            let mut timeout = timeout;
+            igInputDouble(const_cstr!("Timeout (s)").as_ptr(), &mut timeout, 1.0, 10.0,
+                          const_cstr!("%.1f").as_ptr(), 0 as _);
+            if igIsItemEdited() { cfg.timeout = Some(timeout.max(0.0)); modified = true; }
+        }
+    }
+
+    if modified {
+        analysis.edit_model(|m| {
+            m.plans.get_mut(plan_idx)?.config = cfg;
+            Some(EditClass::PlanConfig(plan_idx))
+        });
+    }
+    Some(())
+}
+
+/// Explain the planning result: candidates found, reasons for rejecting
+/// them, and conflicting ordering constraints.
+fn plan_diagnostics_tooltip(analysis :&Analysis, plan_idx :usize) -> Option<()> {
+    let (generation, diagnostics) = analysis.data().plandiagnostics.get(plan_idx)?.as_ref()?;
+    if generation != analysis.generation() { return None; }
+    let plan = analysis.model().plans.get(plan_idx)?;
+    unsafe {
+        igBeginTooltip();
+        for line in diagnostics.lines(plan) {
+            widgets::show_text(&line);
+        }
+        igEndTooltip();
+    }
+    Some(())
+}
+
+/// Add a planned dispatch to the model's manual dispatches. Returns the new dispatch id.
+fn copy_as_manual(analysis :&mut Analysis, plan_idx :usize, dispatch_idx :usize) -> Option<usize> {
+    let (_,il) = analysis.data().interlocking.as_ref()?;
+    let (_,dispatches) = analysis.data().plandispatches.get(plan_idx)?.as_ref()?;
+    let plan_name = &analysis.model().plans.get(plan_idx)?.name;
+    let planned = dispatches.get(dispatch_idx)?;
+    let name = format!("{} {}", plan_name, planned.dispatch.name.to_lowercase());
+    let dispatch = planned.to_manual(il, name);
+
+    let mut model = analysis.model().clone();
+    let id = model.dispatches.insert(dispatch);
+    analysis.set_model(model, None);
+    Some(id)
+}
+
+enum PlanDispatchAction {
+    CopyAsManual(usize),
+    CycleTime(usize),
+}
+
+/// Select among the plan's dispatches. Returns an action if the selected
+/// dispatch should be copied into a manual dispatch, or if its minimum
+/// cycle time should be computed.
+fn plan_dispatches(config :&Config, analysis :&Analysis, adv :&mut AutoDispatchView) -> Option<PlanDispatchAction> {
+    let mut action = None;
     unsafe {
         if let Some(Some((generation,dispatches))) = analysis.data().plandispatches.get(adv.plan_idx) {
             planning_icon(config,analysis,*generation,dispatches);
+            if igIsItemHovered(0) {
+                plan_diagnostics_tooltip(analysis, adv.plan_idx);
+            }
             igSameLine(0.0,-1.0);
 
             let dispatch_idx = if let Some(ManualDispatchView { dispatch_idx, .. }) = &adv.dispatch {
                 Some(*dispatch_idx) } else { None };
-            let dispatch_name = if let Some(dispatch_idx) = dispatch_idx {
-                CString::new(format!("Dispatch {}", dispatch_idx)).unwrap()
-            } else { CString::new(format!("None")).unwrap() };
+            let dispatch_name = match dispatch_idx.and_then(|i| dispatches.get(i)) {
+                Some(d) => CString::new(d.dispatch.name.clone()).unwrap(),
+                None => CString::new(format!("None")).unwrap(),
+            };
 
             if igBeginCombo(const_cstr!("##chtr").as_ptr(), dispatch_name.as_ptr(), 0) {
                 if igSelectable(const_cstr!("None").as_ptr(), dispatch_idx.is_none(), 0 as _, ImVec2::zero()) {
 
                     adv.dispatch = None;
+                    adv.dispatch_key = None;
                 }
 
                 for (di,d) in dispatches.iter().enumerate() {
@@ -360,17 +520,55 @@ fn plan_dispatches(config :&Config, analysis :&Analysis, adv :&mut AutoDispatchV
                                  dispatch_idx == Some(di), 
                                  0 as _ , ImVec2::zero()) {
                         adv.dispatch = Some(ManualDispatchView::new(di));
+                        adv.dispatch_key = Some(crate::document::plan::dispatch_key(&d.dispatch.commands));
                     }
 
                     igSameLine(0.0,-1.0);
-                    widgets::show_text(&format!("Dispatch {}", di));
+                    widgets::show_text(&d.dispatch.name);
                     igPopID();
                 }
 
                 igEndCombo();
             }
+
+            if let Some(dispatch_idx) = dispatch_idx {
+                igSameLine(0.0,-1.0);
+                if igButton(const_cstr!("\u{f0c5} Manual").as_ptr(), ImVec2::zero()) {
+                    action = Some(PlanDispatchAction::CopyAsManual(dispatch_idx));
+                }
+                if igIsItemHovered(0) {
+                    igBeginTooltip();
+                    widgets::show_text("Copy this dispatch as a manual dispatch for editing.");
+                    igEndTooltip();
+                }
+
+                let periodic = analysis.model().plans.get(adv.plan_idx)
+                    .map(|p| p.config.cycle_time.is_some()).unwrap_or(false);
+                if let (true, Some(d)) = (periodic, dispatches.get(dispatch_idx)) {
+                    igSameLine(0.0,-1.0);
+                    let key = crate::document::plan::dispatch_key(&d.dispatch.commands);
+                    match analysis.data().cycle_times.get(&(adv.plan_idx, key)) {
+                        None => {
+                            if action.is_none() { action = Some(PlanDispatchAction::CycleTime(dispatch_idx)); }
+                            widgets::show_text("Finding cycle time...");
+                        },
+                        Some(None) => widgets::show_text("Finding cycle time..."),
+                        Some(Some(Ok(Some(t)))) => widgets::show_text(&format!("Min. cycle time {:.0} s", t)),
+                        Some(Some(Ok(None))) => widgets::show_text("Cannot be repeated"),
+                        Some(Some(Err(e))) => {
+                            widgets::show_text("Cycle time failed");
+                            if igIsItemHovered(0) {
+                                igBeginTooltip();
+                                widgets::show_text(e);
+                                igEndTooltip();
+                            }
+                        },
+                    }
+                }
+            }
         }
     }
+    action
 }
 
 fn visit_move(m: &mut Model, plan :usize, source :VisitKey, t_train_idx: usize, idx :Option<usize>) -> Option<()> {
@@ -397,7 +595,9 @@ fn visit_move(m: &mut Model, plan :usize, source :VisitKey, t_train_idx: usize,
 }
 
 fn rename_train_visit(plan :&mut PlanSpec, train :usize, visit :usize, new_train :usize, new_visit :usize) {
-    for (a,b,_) in plan.order.iter_mut() {
+    let visits = plan.order.iter_mut().map(|(a,b,_)| (a,b))
+        .chain(plan.min_times.iter_mut().map(|(a,b,_)| (a,b)));
+    for (a,b) in visits {
         if a.0 == train && a.1 == visit {
             a.0 = new_train;
             a.1 = new_visit;
@@ -415,6 +615,7 @@ fn remove_ordering_train(plan :&mut PlanSpec, train :usize) {
         let b = b.0 == train;
         !a && !b
     });
+    plan.min_times.retain(|(a,b,_)| a.0 != train && b.0 != train);
 }
 
 fn remove_ordering_at(plan :&mut PlanSpec, train :usize, visit :usize) {
@@ -423,6 +624,7 @@ fn remove_ordering_at(plan :&mut PlanSpec, train :usize, visit :usize) {
         let b = b.0 == train && b.1 == visit;
         !a && !b
     });
+    plan.min_times.retain(|(a,b,_)| *a != (train,visit) && *b != (train,visit));
 }
 
 fn visit_merge(m :&mut Model, plan :usize, source :VisitKey, target :VisitKey) -> Option<()> {
@@ -602,6 +804,15 @@ unsafe {
             igPopID();
         }
 
+        if let Some(dwell) = visit.dwell {
+            igButton(const_cstr!("\u{f017}").as_ptr(), ImVec2::zero());
+            if igIsItemHovered(0) {
+                igBeginTooltip();
+                widgets::show_text(&format!("Dwell {:.0} s", dwell));
+                igEndTooltip();
+            }
+        }
+
         igEndChild();
     }
     igPopStyleColor(1);
@@ -619,6 +830,19 @@ unsafe {
                     auto_dispatch.action = PlanViewAction::DragFrom(key,pos);
                 }
 
+                let mut has_dwell = visit.dwell.is_some();
+                if igCheckbox(const_cstr!("\u{f017} Dwell").as_ptr(), &mut has_dwell) {
+                    *action = Some(Action::VisitDwell { key, dwell: if has_dwell { Some(60.0) } else { None } });
+                }
+                if let Some(dwell) = visit.dwell {
+                    let mut dwell = dwell;
+                    igInputDouble(const_cstr!("Dwell time (s)").as_ptr(), &mut dwell, 1.0, 10.0,
+                                  const_cstr!("%.0f").as_ptr(), 0 as _);
+                    if igIsItemEdited() {
+                        *action = Some(Action::VisitDwell { key, dwell: Some(dwell.max(0.0)) });
+                    }
+                }
+
                 widgets::sep();
                 
                 if key.location.is_some() {
@@ -0,0 +1,99 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+use crate::gui::widgets;
+use crate::gui::widgets::Draw;
+use crate::gui::infrastructure::draw;
+use crate::document::analysis::Analysis;
+use crate::document::infview::InfView;
+use crate::document::interlocking::*;
+use crate::document::issues::ref_name;
+use crate::config::*;
+
+pub fn route_name(info :&RouteInfo) -> String {
+    format!("{} to {} (alt. {})", ref_name(&info.id.from), ref_name(&info.id.to), info.id.alternative)
+}
+
+/// Matrix of conflicting routes. Hovering a cell highlights both routes
+/// on the infrastructure canvas.
+pub fn conflicts_window(popen :&mut bool, config :&Config, analysis :&Analysis,
+                        inf_canvas :Option<&Draw>, inf_view :&InfView) {
+    if !*popen { return; }
+    unsafe {
+    widgets::next_window_center_when_appearing();
+    igBegin(const_cstr!("Route conflicts").as_ptr(), popen as *mut bool, 
+            ImGuiWindowFlags__ImGuiWindowFlags_HorizontalScrollbar as _);
+
+    let il = match analysis.data().interlocking.as_ref() {
+        Some((_,il)) => il,
+        None => {
+            widgets::show_text("No interlocking available.");
+            igEnd();
+            return;
+        },
+    };
+
+    if il.routes.is_empty() {
+        widgets::show_text("No routes.");
+    } else {
+        widgets::show_text("O: opposing, P: switch position, X: crossing, S: shared section, V: overlap");
+        widgets::sep();
+
+        let label_width = 40.0;
+        let cell_width = 24.0;
+        let n = il.routes.len();
+
+        for j in 0..n {
+            igSameLine(label_width + j as f32 * cell_width, -1.0);
+            widgets::show_text(&format!("{}", j));
+        }
+        igNewLine();
+
+        for i in 0..n {
+            widgets::show_text(&format!("{}", i));
+            if igIsItemHovered(0) {
+                igBeginTooltip();
+                widgets::show_text(&route_name(&il.routes[i]));
+                igEndTooltip();
+                if let Some(inf_canvas) = inf_canvas {
+                    draw::route(config, analysis, inf_view, inf_canvas, i);
+                }
+            }
+
+            for j in 0..n {
+                igSameLine(label_width + j as f32 * cell_width, -1.0);
+                igPushIDInt((i*n + j) as _);
+                let conflict = if i == j { None } else { il.conflict(i,j) };
+                let label = match conflict {
+                    _ if i == j => const_cstr!("-"),
+                    Some(RouteConflict::Opposing) => const_cstr!("O"),
+                    Some(RouteConflict::SwitchPosition) => const_cstr!("P"),
+                    Some(RouteConflict::Crossing) => const_cstr!("X"),
+                    Some(RouteConflict::SharedSection) => const_cstr!("S"),
+                    Some(RouteConflict::Overlap) => const_cstr!("V"),
+                    None => const_cstr!("."),
+                };
+                igSelectable(label.as_ptr(), false, 0 as _, ImVec2 { x: cell_width - 4.0, y: 0.0 });
+                if igIsItemHovered(0) {
+                    igBeginTooltip();
+                    widgets::show_text(&format!("Route {}: {}", i, route_name(&il.routes[i])));
+                    widgets::show_text(&format!("Route {}: {}", j, route_name(&il.routes[j])));
+                    if i != j {
+                        widgets::show_text(&match conflict {
+                            Some(c) => format!("Conflict: {}", c.name()),
+                            None => format!("No conflict"),
+                        });
+                    }
+                    igEndTooltip();
+                    if let Some(inf_canvas) = inf_canvas {
+                        draw::route(config, analysis, inf_view, inf_canvas, i);
+                        if i != j { draw::route(config, analysis, inf_view, inf_canvas, j); }
+                    }
+                }
+                igPopID();
+            }
+        }
+    }
+
+    igEnd();
+    }
+}
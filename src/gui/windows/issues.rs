@@ -0,0 +1,67 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+use crate::gui::widgets;
+use crate::gui::widgets::Draw;
+use crate::gui::infrastructure::draw::box_around;
+use crate::document::analysis::Analysis;
+use crate::document::infview::InfView;
+use crate::document::issues::*;
+use crate::document::model::Ref;
+use crate::config::*;
+
+pub fn issues_window(popen :&mut bool, config :&Config, analysis :&Analysis,
+                     inf_canvas :Option<&Draw>, inf_view :&mut InfView) {
+    if !*popen { return; }
+    unsafe {
+    widgets::next_window_center_when_appearing();
+    igBegin(const_cstr!("Issues").as_ptr(), popen as *mut bool, 0 as _);
+
+    let issues = &analysis.data().issues;
+    let route_issues = analysis.data().interlocking.as_ref()
+        .map(|(_,il)| il.route_issues.as_slice()).unwrap_or(&[]);
+    if issues.is_empty() && route_issues.is_empty() {
+        widgets::show_text("No issues found.");
+    }
+    for (i,issue) in issues.iter().enumerate() {
+        igPushIDInt(i as _);
+        show_issue(config, issue.location(), &issue.message(), inf_canvas, inf_view);
+        igPopID();
+    }
+
+    if !route_issues.is_empty() {
+        widgets::sep();
+        widgets::show_text("Interlocking");
+        for (i,issue) in route_issues.iter().enumerate() {
+            igPushIDInt((issues.len() + i) as _);
+            show_issue(config, issue.location(), &issue.message(), inf_canvas, inf_view);
+            igPopID();
+        }
+    }
+
+    igEnd();
+    }
+}
+
+/// List item for an issue. Hovering highlights the location on the
+/// infrastructure canvas, clicking selects it and moves the view to it.
+pub fn show_issue(config :&Config, location :Option<Ref>, message :&str,
+                  inf_canvas :Option<&Draw>, inf_view :&mut InfView) {
+    unsafe {
+        let clicked = igSelectable(const_cstr!("").as_ptr(), false, 0 as _, ImVec2::zero());
+        if let Some(loc) = location {
+            if igIsItemHovered(0) {
+                if let Some(inf_canvas) = inf_canvas {
+                    box_around(config, inf_canvas, inf_view, ref_location(&loc));
+                }
+            }
+            if clicked {
+                inf_view.selection = std::iter::once(loc).collect();
+                if let Some(inf_canvas) = inf_canvas {
+                    inf_view.view.center_on(ref_location(&loc), inf_canvas.size);
+                }
+            }
+        }
+        igSameLine(0.0,-1.0);
+        widgets::show_text(message);
+    }
+}
@@ -4,4 +4,8 @@ pub mod config;
 pub mod quit;
 pub mod logview;
 pub mod synthesis;
+pub mod issues;
+pub mod settings;
 
+pub mod conflicts;
+pub mod templates;
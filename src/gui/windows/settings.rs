@@ -0,0 +1,110 @@
+use crate::document::Document;
+use crate::document::model::*;
+use const_cstr::*;
+use backend_glfw::imgui::*;
+use crate::gui::widgets;
+
+pub fn edit_settings(doc :&mut Document) {
+    unsafe {
+    let mut new_model = doc.analysis.model().clone();
+    let mut modified = None;
+    let settings = &doc.analysis.model().settings;
+    let format = const_cstr!("%.1f");
+    let defaultopen = ImGuiTreeNodeFlags__ImGuiTreeNodeFlags_DefaultOpen;
+
+    if igCollapsingHeader(const_cstr!("Interlocking").as_ptr(), defaultopen as _) {
+        let mut tolerance = settings.interlocking.section_tolerance;
+        igInputDouble(const_cstr!("Section tolerance (m)").as_ptr(), 
+                      &mut tolerance, 1.0, 10.0, format.as_ptr(), 0 as _);
+        if igIsItemEdited() {
+            new_model.settings.interlocking.section_tolerance = tolerance.max(0.0);
+            modified = Some(EditClass::SectionTolerance);
+        }
+
+        let mut min_length = settings.interlocking.route_minimum_length;
+        igInputDouble(const_cstr!("Min. route length (m)").as_ptr(), 
+                      &mut min_length, 1.0, 10.0, format.as_ptr(), 0 as _);
+        if igIsItemEdited() {
+            new_model.settings.interlocking.route_minimum_length = min_length.max(0.0);
+            modified = Some(EditClass::RouteMinimumLength);
+        }
+
+        let mut overlap_length = settings.interlocking.overlap_length;
+        igInputDouble(const_cstr!("Overlap length (m)").as_ptr(), 
+                      &mut overlap_length, 10.0, 50.0, format.as_ptr(), 0 as _);
+        if igIsItemEdited() {
+            new_model.settings.interlocking.overlap_length = overlap_length.max(0.0);
+            modified = Some(EditClass::OverlapLength);
+        }
+
+        let mut has_timeout = settings.interlocking.overlap_timeout.is_some();
+        if igCheckbox(const_cstr!("Overlap release timeout").as_ptr(), &mut has_timeout) {
+            new_model.settings.interlocking.overlap_timeout = 
+                if has_timeout { Some(30.0) } else { None };
+            modified = Some(EditClass::OverlapTimeout);
+        }
+        if let Some(timeout) = settings.interlocking.overlap_timeout {
+            let mut timeout = timeout;
+            igInputDouble(const_cstr!("Overlap timeout (s)").as_ptr(), 
+                          &mut timeout, 1.0, 10.0, format.as_ptr(), 0 as _);
+            if igIsItemEdited() {
+                new_model.settings.interlocking.overlap_timeout = Some(timeout.max(0.0));
+                modified = Some(EditClass::OverlapTimeout);
+            }
+        }
+
+        let mut flank = settings.interlocking.flank_protection;
+        if igCheckbox(const_cstr!("Flank protection (switches only)").as_ptr(), &mut flank) {
+            new_model.settings.interlocking.flank_protection = flank;
+            modified = Some(EditClass::FlankProtection);
+        }
+        if igIsItemHovered(0) {
+            igBeginTooltip();
+            widgets::show_text("Lock switches leading away from the route.\nFlank signals are only shown, they are not locked\nand do not make routes conflict.");
+            igEndTooltip();
+        }
+    }
+
+    if igCollapsingHeader(const_cstr!("Blocking times").as_ptr(), defaultopen as _) {
+        let mut route_setting = settings.timing.route_setting;
+        igInputDouble(const_cstr!("Route setting time (s)").as_ptr(), 
+                      &mut route_setting, 1.0, 10.0, format.as_ptr(), 0 as _);
+        if igIsItemEdited() {
+            new_model.settings.timing.route_setting = route_setting.max(0.0);
+            modified = Some(EditClass::RouteSettingTime);
+        }
+
+        let mut sight = settings.timing.sight;
+        igInputDouble(const_cstr!("Sight and reaction time (s)").as_ptr(), 
+                      &mut sight, 1.0, 10.0, format.as_ptr(), 0 as _);
+        if igIsItemEdited() {
+            new_model.settings.timing.sight = sight.max(0.0);
+            modified = Some(EditClass::SightTime);
+        }
+
+        let mut release = settings.timing.release;
+        igInputDouble(const_cstr!("Release time (s)").as_ptr(), 
+                      &mut release, 1.0, 10.0, format.as_ptr(), 0 as _);
+        if igIsItemEdited() {
+            new_model.settings.timing.release = release.max(0.0);
+            modified = Some(EditClass::ReleaseTime);
+        }
+    }
+
+    if modified.is_some() {
+        doc.analysis.set_model(new_model, modified);
+    }
+    }
+}
+
+pub fn edit_settings_window(popen :&mut bool, doc :&mut Document) {
+    if !*popen { return; }
+    unsafe {
+    widgets::next_window_center_when_appearing();
+    igBegin(const_cstr!("Document settings").as_ptr(), popen as *mut bool, 0 as _);
+
+    edit_settings(doc);
+
+    igEnd();
+    }
+}
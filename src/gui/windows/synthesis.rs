@@ -180,15 +180,22 @@ impl SynthesisWindow {
             .cloned().collect::<Vec<_>>();
 
         self.thread_pool.execute(move || {
-            use crate::document::topology;
+            use crate::document::{topology, dynamics};
             let topo = topology::convert(&model, 50.0).unwrap();
-            let vehicles = model.vehicles.iter().cloned().collect::<Vec<_>>();
+            let vehicles = dynamics::vehicles(&model);
 
-            let result = full_synthesis(&SynthesisBackground { topology: &topo, plans: &plans, vehicles: &vehicles }, 
-                           |msg| tx.send(msg).is_ok());
+            let bg = SynthesisBackground { topology: &topo, plans: &plans, vehicles: &vehicles,
+                                           interlocking: &model.settings.interlocking };
+            let result = full_synthesis(&bg, |msg| tx.send(msg).is_ok());
 
             if let Err(e) = result {
                 error!("full_synthesis: {:?}", e);
+                let msg = match e {
+                    SynErr::Aborted => None,
+                    SynErr::Model(issue) => Some(format!("Synthesis failed: {}", issue.message())),
+                    SynErr::Plan(err) => Some(format!("Synthesis failed: could not convert plans ({:?})", err)),
+                };
+                if let Some(msg) = msg { let _ = tx.send(FullSynMsg::S(msg)); }
             }
 
         });
@@ -0,0 +1,199 @@
+use backend_glfw::imgui::*;
+use const_cstr::*;
+use std::ffi::CString;
+
+use crate::gui::widgets;
+use crate::document::*;
+use crate::document::model::*;
+use crate::document::analysis::Analysis;
+use crate::document::infview::InfView;
+use crate::document::issues::ref_name;
+use crate::document::plantemplates::PlanTemplate;
+
+/// Create plans from templates of common operational patterns.
+pub struct TemplatesWindow {
+    template :usize,
+    a :Option<Pt>,
+    b :Option<Pt>,
+    station :Option<Ref>,
+    vehicles :[Option<ListId>;2],
+    headway :f64,
+    trains_per_hour :usize,
+    routes :Vec<(Pt,Pt)>,
+}
+
+impl TemplatesWindow {
+    pub fn new(model :&Model) -> TemplatesWindow {
+        let vehicle = model.vehicles.iter().next().map(|(id,_)| *id);
+        TemplatesWindow {
+            template: 0,
+            a: None,
+            b: None,
+            station: None,
+            vehicles: [vehicle, vehicle],
+            headway: 300.0,
+            trains_per_hour: 4,
+            routes: Vec::new(),
+        }
+    }
+
+    fn get_template(&self) -> Option<PlanTemplate> {
+        Some(match self.template {
+            0 => PlanTemplate::Crossing { a: self.a?, b: self.b?, station: self.station?,
+                                          vehicles: self.vehicles },
+            1 => PlanTemplate::Overtaking { a: self.a?, b: self.b?, station: self.station?,
+                                            vehicles: self.vehicles },
+            2 => PlanTemplate::Following { a: self.a?, b: self.b?, headway: self.headway,
+                                           vehicles: self.vehicles },
+            _ => {
+                if self.routes.is_empty() { return None; }
+                PlanTemplate::TrainsPerHour { routes: self.routes.clone(),
+                                              trains_per_hour: self.trains_per_hour,
+                                              vehicle: self.vehicles[0] }
+            },
+        })
+    }
+
+    /// Returns false when the window is closed.
+    pub fn draw(&mut self, analysis :&mut Analysis, inf_view :&InfView,
+                dispatch_view :&mut Option<DispatchView>) -> bool {
+        let mut keep_open = true;
+        let mut create = None;
+        unsafe {
+            widgets::next_window_center_when_appearing();
+            igBegin(const_cstr!("Plan templates").as_ptr(), &mut keep_open as _, 0 as _);
+
+            let mut boundaries = analysis.data().topology.as_ref()
+                .map(|(_,topo)| topo.locations.iter()
+                     .filter(|(_,(nd,_))| *nd == NDType::OpenEnd)
+                     .map(|(pt,_)| *pt).collect::<Vec<_>>())
+                .unwrap_or(Vec::new());
+            boundaries.sort_by_key(|pt| (pt.x, pt.y));
+
+            for (i,name) in PlanTemplate::NAMES.iter().enumerate() {
+                let name = CString::new(*name).unwrap();
+                if igRadioButtonBool(name.as_ptr(), self.template == i) { self.template = i; }
+                if i+1 < PlanTemplate::NAMES.len() { igSameLine(0.0,-1.0); }
+            }
+            widgets::sep();
+
+            let model = analysis.model();
+            if self.template < 3 {
+                boundary_combo(const_cstr!("From").as_ptr(), &boundaries, &mut self.a);
+                boundary_combo(const_cstr!("To").as_ptr(), &boundaries, &mut self.b);
+            }
+
+            if self.template < 2 {
+                widgets::show_text(&format!("Station: {}",
+                    self.station.as_ref().map(ref_name).unwrap_or(format!("none"))));
+                igSameLine(0.0,-1.0);
+                if igButton(const_cstr!("Use selection").as_ptr(), ImVec2::zero()) {
+                    if let Some(r) = inf_view.selection.iter().next() {
+                        self.station = Some(*r);
+                    }
+                }
+            }
+
+            if self.template == 2 {
+                igInputDouble(const_cstr!("Headway (s)").as_ptr(), &mut self.headway, 10.0, 60.0,
+                              const_cstr!("%.0f").as_ptr(), 0 as _);
+                if igIsItemEdited() { self.headway = self.headway.max(0.0); }
+            }
+
+            if self.template == 3 {
+                let mut n = self.trains_per_hour as i32;
+                igInputInt(const_cstr!("Trains per hour").as_ptr(), &mut n, 1, 5, 0 as _);
+                if igIsItemEdited() { self.trains_per_hour = n.max(1) as usize; }
+
+                let mut delete = None;
+                for (i,(from,to)) in self.routes.iter().enumerate() {
+                    igPushIDInt(i as _);
+                    if igButton(const_cstr!("\u{f2ed}").as_ptr(), ImVec2::zero()) { delete = Some(i); }
+                    igSameLine(0.0,-1.0);
+                    widgets::show_text(&format!("{} to {}", ref_name(&Ref::Node(*from)),
+                                                ref_name(&Ref::Node(*to))));
+                    igPopID();
+                }
+                if let Some(i) = delete { self.routes.remove(i); }
+
+                boundary_combo(const_cstr!("From").as_ptr(), &boundaries, &mut self.a);
+                boundary_combo(const_cstr!("To").as_ptr(), &boundaries, &mut self.b);
+                if let (Some(a),Some(b)) = (self.a, self.b) {
+                    if igButton(const_cstr!("\u{f0fe} Add").as_ptr(), ImVec2::zero()) {
+                        self.routes.push((a,b));
+                    }
+                }
+            }
+
+            widgets::sep();
+            let n_vehicles = if self.template == 3 { 1 } else { 2 };
+            for i in 0..n_vehicles {
+                igPushIDInt(i as _);
+                vehicle_combo(const_cstr!("Vehicle").as_ptr(), model, &mut self.vehicles[i]);
+                igPopID();
+            }
+
+            widgets::sep();
+            match self.get_template() {
+                Some(template) => {
+                    if igButton(const_cstr!("Create plan").as_ptr(), ImVec2::zero()) {
+                        create = Some(template);
+                    }
+                },
+                None => { widgets::show_text("Choose locations to create a plan."); },
+            }
+
+            igEnd();
+        }
+
+        if let Some(template) = create {
+            let mut model = analysis.model().clone();
+            let name = format!("{} {}", template.name(), model.plans.next_id()+1);
+            let id = model.plans.insert(template.plan(name));
+            analysis.set_model(model, None);
+            *dispatch_view = Some(DispatchView::Auto(AutoDispatchView {
+                plan_idx: id,
+                dispatch: None,
+                dispatch_key: None,
+                action: PlanViewAction::None,
+            }));
+        }
+
+        keep_open
+    }
+}
+
+fn boundary_combo(label :*const i8, boundaries :&[Pt], value :&mut Option<Pt>) {
+    unsafe {
+        let current = CString::new(value.map(|pt| ref_name(&Ref::Node(pt)))
+                                   .unwrap_or(format!("None"))).unwrap();
+        if igBeginCombo(label, current.as_ptr(), 0 as _) {
+            for pt in boundaries.iter() {
+                let name = CString::new(ref_name(&Ref::Node(*pt))).unwrap();
+                if igSelectable(name.as_ptr(), *value == Some(*pt), 0 as _, ImVec2::zero()) {
+                    *value = Some(*pt);
+                }
+            }
+            igEndCombo();
+        }
+    }
+}
+
+fn vehicle_combo(label :*const i8, model :&Model, value :&mut Option<ListId>) {
+    unsafe {
+        let current = value.and_then(|id| model.vehicles.get(id)).map(|v| v.name.clone())
+            .unwrap_or(format!("Default train"));
+        let current = CString::new(current).unwrap();
+        if igBeginCombo(label, current.as_ptr(), 0 as _) {
+            for (id,v) in model.vehicles.iter() {
+                igPushIDInt(*id as _);
+                let name = CString::new(v.name.clone()).unwrap();
+                if igSelectable(name.as_ptr(), *value == Some(*id), 0 as _, ImVec2::zero()) {
+                    *value = Some(*id);
+                }
+                igPopID();
+            }
+            igEndCombo();
+        }
+    }
+}
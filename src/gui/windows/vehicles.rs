@@ -1,10 +1,14 @@
 use crate::document::Document;
 use crate::document::model::*;
+use crate::document::dynamics;
+use crate::vehiclelib::*;
 use const_cstr::*;
 use backend_glfw::imgui::*;
+use log::*;
+use std::ffi::CString;
 use crate::gui::widgets;
 
-pub fn edit_vehicles(doc :&mut Document) {
+pub fn edit_vehicles(doc :&mut Document, library :&mut VehicleLibrary) {
     unsafe {
     let mut new_model = doc.analysis.model().clone();
     let mut modified = None;
@@ -50,6 +54,30 @@ pub fn edit_vehicles(doc :&mut Document) {
                 new_model.vehicles.get_mut(*i).unwrap().max_vel = vel;
                 modified = Some(EditClass::VehicleVel(*i));
             }
+
+            let mut has_dynamics = v.dynamics.is_some();
+            igCheckbox(const_cstr!("Gradient-dependent dynamics").as_ptr(), &mut has_dynamics);
+            if igIsItemEdited() {
+                new_model.vehicles.get_mut(*i).unwrap().dynamics = 
+                    if has_dynamics { Some(Default::default()) } else { None };
+                modified = Some(EditClass::VehicleDynamics(*i));
+            }
+            if let Some(d) = &v.dynamics {
+                if let Some(d) = edit_dynamics(d) {
+                    new_model.vehicles.get_mut(*i).unwrap().dynamics = Some(d);
+                    modified = Some(EditClass::VehicleDynamics(*i));
+                }
+                match dynamics::equivalent_vehicle(v, 0.0, 0.0) {
+                    Ok(eq) => widgets::show_text(&format!("On level track: accel {:.2}, brake {:.2}, max.vel {:.1}",
+                                                          eq.max_acc, eq.max_brk, eq.max_vel)),
+                    Err(e) => widgets::show_text(&e),
+                }
+            }
+
+            if igButton(const_cstr!("Add to library").as_ptr(), ImVec2::zero()) {
+                library.add(v.clone());
+                library.save();
+            }
         }
 
         igPopID();
@@ -71,6 +99,7 @@ pub fn edit_vehicles(doc :&mut Document) {
                 max_acc: 1.0,
                 max_brk: 0.5,
                 max_vel: 50.0,
+                dynamics: None,
             });
             m.vehicles.get_mut(id).unwrap().name = format!("Vehicle {}", id);
             None
@@ -81,13 +110,228 @@ pub fn edit_vehicles(doc :&mut Document) {
 }
 
 
-pub fn edit_vehicles_window(popen :&mut bool, doc :&mut Document) {
+/// Returns the new values if edited.
+fn edit_dynamics(d :&VehicleDynamics) -> Option<VehicleDynamics> {
+    let mut d = d.clone();
+    let mut edited = false;
+    unsafe {
+        let format = const_cstr!("%.3f");
+        igSliderFloat(const_cstr!("Mass (t)").as_ptr(), 
+                      &mut d.mass as *mut _, 10.0, 5000.0, format.as_ptr(), 1.0);
+        edited |= igIsItemEdited();
+        igSliderFloat(const_cstr!("Rotating mass").as_ptr(), 
+                      &mut d.rotating_mass as *mut _, 1.0, 1.3, format.as_ptr(), 1.0);
+        edited |= igIsItemEdited();
+        igInputFloat(const_cstr!("Davis A (kN)").as_ptr(), &mut d.davis.0, 0.1, 1.0,
+                     format.as_ptr(), 0 as _);
+        edited |= igIsItemEdited();
+        igInputFloat(const_cstr!("Davis B (kN/(m/s))").as_ptr(), &mut d.davis.1, 0.01, 0.1,
+                     format.as_ptr(), 0 as _);
+        edited |= igIsItemEdited();
+        igInputFloat(const_cstr!("Davis C (kN/(m/s)^2)").as_ptr(), &mut d.davis.2, 0.001, 0.01,
+                     format.as_ptr(), 0 as _);
+        edited |= igIsItemEdited();
+
+        igPushIDInt(1);
+        edited |= edit_curve("Tractive effort (m/s, kN)", &mut d.tractive_effort);
+        igPopID();
+        igPushIDInt(2);
+        edited |= edit_curve("Brake force (m/s, kN), empty for constant braking", &mut d.brake_effort);
+        igPopID();
+    }
+    if edited { Some(d) } else { None }
+}
+
+/// Edit the points of a speed-dependent force curve. Returns true if edited.
+fn edit_curve(label :&str, curve :&mut Vec<(f32,f32)>) -> bool {
+    let mut edited = false;
+    unsafe {
+        widgets::show_text(label);
+        let mut delete = None;
+        for (j,(vel,force)) in curve.iter_mut().enumerate() {
+            igPushIDInt(j as _);
+            igPushItemWidth(100.0);
+            igInputFloat(const_cstr!("##vel").as_ptr(), vel, 1.0, 10.0, const_cstr!("%.1f").as_ptr(), 0 as _);
+            edited |= igIsItemEdited();
+            igSameLine(0.0,-1.0);
+            igInputFloat(const_cstr!("##force").as_ptr(), force, 10.0, 100.0, const_cstr!("%.1f").as_ptr(), 0 as _);
+            edited |= igIsItemEdited();
+            igPopItemWidth();
+            igSameLine(0.0,-1.0);
+            if igButton(const_cstr!("\u{f2ed}").as_ptr(), ImVec2::zero()) { delete = Some(j); }
+            igPopID();
+        }
+        if let Some(j) = delete { curve.remove(j); edited = true; }
+        if igButton(const_cstr!("Add point").as_ptr(), ImVec2::zero()) {
+            let last = curve.last().cloned().unwrap_or((0.0, 100.0));
+            curve.push((last.0 + 10.0, last.1));
+            edited = true;
+        }
+    }
+    if edited {
+        curve.sort_by(|a,b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    edited
+}
+
+/// Vehicles in the shared library, and compositions of library vehicles,
+/// which can be added to the document.
+pub fn edit_library(doc :&mut Document, library :&mut VehicleLibrary) {
+    unsafe {
+    if !igCollapsingHeader(const_cstr!("Vehicle library").as_ptr(), 0) { return; }
+
+    let mut changed = false;
+    let mut add = None;
+    let mut delete_vehicle = None;
+    igPushIDInt(1);
+    for (j,v) in library.vehicles.iter().enumerate() {
+        igPushIDInt(j as _);
+        if igButton(const_cstr!("\u{f0fe}").as_ptr(), ImVec2::zero()) { add = Some(Ok(v.clone())); }
+        igSameLine(0.0,-1.0);
+        if igButton(const_cstr!("\u{f2ed}").as_ptr(), ImVec2::zero()) { delete_vehicle = Some(j); }
+        igSameLine(0.0,-1.0);
+        widgets::show_text(&v.name);
+        igPopID();
+    }
+    igPopID();
+    if library.vehicles.is_empty() { widgets::show_text("No vehicles in library."); }
+    if let Some(j) = delete_vehicle { library.vehicles.remove(j); changed = true; }
+
+    widgets::sep();
+    widgets::show_text("Compositions");
+    let names = library.vehicles.iter().map(|v| v.name.clone()).collect::<Vec<_>>();
+    let mut delete_composition = None;
+    igPushIDInt(2);
+    for (j,c) in library.compositions.iter_mut().enumerate() {
+        igPushIDInt(j as _);
+        if let Some(name) = widgets::edit_text(const_cstr!("Name").as_ptr(), c.name.clone()) {
+            c.name = name;
+            changed = true;
+        }
+
+        let mut delete_unit = None;
+        for (k,unit) in c.units.iter_mut().enumerate() {
+            igPushIDInt(k as _);
+            igPushItemWidth(150.0);
+            let current = CString::new(unit.vehicle.clone()).unwrap();
+            if igBeginCombo(const_cstr!("##unit").as_ptr(), current.as_ptr(), 0 as _) {
+                for name in names.iter() {
+                    let name_c = CString::new(name.clone()).unwrap();
+                    if igSelectable(name_c.as_ptr(), *name == unit.vehicle, 0 as _, ImVec2::zero()) {
+                        unit.vehicle = name.clone();
+                        changed = true;
+                    }
+                }
+                igEndCombo();
+            }
+            igSameLine(0.0,-1.0);
+            let mut count = unit.count as i32;
+            igInputInt(const_cstr!("##count").as_ptr(), &mut count, 1, 1, 0 as _);
+            if igIsItemEdited() { unit.count = count.max(1) as usize; changed = true; }
+            igPopItemWidth();
+            igSameLine(0.0,-1.0);
+            if igButton(const_cstr!("\u{f2ed}").as_ptr(), ImVec2::zero()) { delete_unit = Some(k); }
+            igPopID();
+        }
+        if let Some(k) = delete_unit { c.units.remove(k); changed = true; }
+
+        if let Some(name) = names.first() {
+            if igButton(const_cstr!("Add unit").as_ptr(), ImVec2::zero()) {
+                c.units.push(CompositionUnit { vehicle: name.clone(), count: 1 });
+                changed = true;
+            }
+            igSameLine(0.0,-1.0);
+        }
+        if igButton(const_cstr!("Add to document").as_ptr(), ImVec2::zero()) {
+            add = Some(Err(c.clone()));
+        }
+        igSameLine(0.0,-1.0);
+        if igButton(const_cstr!("Delete").as_ptr(), ImVec2::zero()) { delete_composition = Some(j); }
+        widgets::sep();
+        igPopID();
+    }
+    igPopID();
+    if let Some(j) = delete_composition { library.compositions.remove(j); changed = true; }
+
+    if igButton(const_cstr!("New composition").as_ptr(), ImVec2::zero()) {
+        let name = format!("Composition {}", library.compositions.len()+1);
+        library.compositions.push(Composition { name, units: Vec::new() });
+        changed = true;
+    }
+
+    if changed { library.save(); }
+
+    let vehicle = match add {
+        Some(Ok(v)) => Some(v),
+        Some(Err(c)) => match library.compose(&c) {
+            Ok(v) => Some(v),
+            Err(e) => { error!("{}", e); None },
+        },
+        None => None,
+    };
+    if let Some(vehicle) = vehicle {
+        doc.analysis.edit_model(|m| { m.vehicles.insert(vehicle); None });
+    }
+    }
+}
+
+/// Add the vehicles and compositions from a TOML vehicle file to the document.
+fn import(doc :&mut Document) -> Result<(), String> {
+    if let Some(filename) = tinyfiledialogs::open_file_dialog("Import vehicles", "",
+                                             Some((&["*.toml"],"TOML files"))) {
+        let s = std::fs::read_to_string(filename).map_err(|e| format!("Could not read file: {}", e))?;
+        let file = VehicleLibrary::from_toml(&s)?;
+        let mut vehicles = file.vehicles.clone();
+        for c in file.compositions.iter() { vehicles.push(file.compose(c)?); }
+        doc.analysis.edit_model(|m| {
+            for v in vehicles { m.vehicles.insert(v); }
+            None
+        });
+    }
+    Ok(())
+}
+
+/// Write the document's vehicles to a TOML vehicle file.
+fn export(doc :&Document) -> Result<(), String> {
+    if let Some(filename) = tinyfiledialogs::save_file_dialog_with_filter("Export vehicles", 
+                                             "vehicles.toml", &["*.toml"], "TOML files") {
+        let file = VehicleLibrary {
+            vehicles: doc.analysis.model().vehicles.iter().map(|(_,v)| v.clone()).collect(),
+            compositions: Vec::new(),
+        };
+        std::fs::write(filename, file.to_toml()?).map_err(|e| format!("Could not write file: {}", e))?;
+    }
+    Ok(())
+}
+
+pub fn edit_vehicles_window(popen :&mut bool, doc :&mut Document, library :&mut Option<VehicleLibrary>) {
     if !*popen { return; }
+    let library = library.get_or_insert_with(VehicleLibrary::load);
     unsafe {
+    let win_flags = ImGuiWindowFlags__ImGuiWindowFlags_MenuBar;
     widgets::next_window_center_when_appearing();
-    igBegin(const_cstr!("Vehicles").as_ptr(), popen as *mut bool, 0 as _);
+    igBegin(const_cstr!("Vehicles").as_ptr(), popen as *mut bool, win_flags as _);
+
+    if igBeginMenuBar() {
+        if igBeginMenu(const_cstr!("File").as_ptr(), true) {
+            if igMenuItemBool(const_cstr!("Import vehicles...").as_ptr(), std::ptr::null(), false, true) {
+                if let Err(e) = import(doc) {
+                    error!("Could not import vehicles: {}", e);
+                }
+            }
+            if igMenuItemBool(const_cstr!("Export vehicles...").as_ptr(), std::ptr::null(), false, true) {
+                if let Err(e) = export(doc) {
+                    error!("Could not export vehicles: {}", e);
+                }
+            }
+            igEndMenu();
+        }
+        igEndMenuBar();
+    }
 
-    edit_vehicles(doc);
+    edit_vehicles(doc, library);
+    widgets::sep();
+    edit_library(doc, library);
 
     igEnd();
     }
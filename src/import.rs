@@ -139,6 +139,7 @@ pub fn load_railml_file(filename :String, tx :mpsc::Sender<ImportState>)  {
     };
     if tx.send(ImportState::Ping).is_err() { return; }
     info!("Converted to topomodel");
+    let switch_speeds = topomodel.switch_speeds.clone();
 
     let plotmodel = match convert_railplot(topomodel) {
         Ok(m) => m,
@@ -175,7 +176,7 @@ pub fn load_railml_file(filename :String, tx :mpsc::Sender<ImportState>)  {
     if tx.send(ImportState::Ping).is_err() { return; }
 
     info!("Found model");
-    let model = match convert_junction(plot) {
+    let model = match convert_junction(plot, &switch_speeds) {
         Ok(m) => m,
         Err(e) => {
             let _ = tx.send(e);
@@ -396,7 +397,8 @@ pub fn round_pt_tol((x,y) :(f64,f64)) -> Result<Pt,()> {
     Ok(glm::vec2(x.round() as _, (-20.0 + y.round()) as _))
 }
 
-pub fn convert_junction(plot :railplotlib::model::SchematicOutput<()>) -> Result<Model, ImportState> {
+pub fn convert_junction(plot :railplotlib::model::SchematicOutput<()>,
+                        switch_speeds :&HashMap<usize,f64>) -> Result<Model, ImportState> {
     debug!("Starting conversion of railplotlib schematic output");
     for (e,pts) in &plot.lines {
         debug!("Line {:?}", pts);
@@ -418,6 +420,13 @@ pub fn convert_junction(plot :railplotlib::model::SchematicOutput<()>) -> Result
             //Shape::Begin | Shape::End =>
         //});
         // TODO
+
+        // Plot nodes are named after their index in the topological model.
+        let speed = n.name.get(1..).and_then(|i| i.parse::<usize>().ok())
+            .and_then(|i| switch_speeds.get(&i));
+        if let Some(speed) = speed {
+            model.node_speeds.insert(pt, speed.round() as u32);
+        }
     }
 
     for (e,pts) in plot.lines {
@@ -5,6 +5,8 @@ mod file;
 mod gui;
 mod util;
 mod import;
+mod batch;
+mod vehiclelib;
 
 mod synthesis;
 
@@ -12,6 +14,12 @@ use log::*;
 use crate::app::*;
 
 fn main() {
+    // Headless command line commands run without a window.
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(exit_code) = batch::run(&args) {
+        std::process::exit(exit_code);
+    }
+
     // Init logging
     let logstring = gui::windows::logview::StringLogger::init(log::LevelFilter::Trace).unwrap();
     info!("Starting {} v{}.", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
@@ -35,8 +43,6 @@ fn main() {
         background_jobs: background_jobs,
     };
 
-
-   let args: Vec<String> = std::env::args().collect();
    let big = args.iter().find(|x| *x == "--big").is_some();
 
     backend_glfw::backend(&app.document.fileinfo.window_title(),
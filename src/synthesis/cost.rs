@@ -1,3 +1,4 @@
+use log::*;
 use crate::synthesis::*;
 use crate::document::history;
 use crate::document::dgraph::DGraph;
@@ -28,7 +29,14 @@ pub fn measure_dispatch(bg :&SynthesisBackground, dgraph :&DGraph, il :&Interloc
 
 pub fn measure(bg :&SynthesisBackground, allplans :&MultiPlan, design :&Design) -> f64 {
     //println!("cost::measure");
-    let (topo,dgraph,il) = create_model(bg,design);
+    let (topo,dgraph,il) = match create_model(bg,design) {
+        Ok(model) => model,
+        Err(issue) => {
+            // A design that cannot be converted into a model cannot run any plan.
+            debug!("cost::measure: design cannot be modelled: {}", issue.message());
+            return std::f64::INFINITY;
+        }
+    };
     let mut total_cost = 0.0;
     //println!("Testing design {:?}", design);
     //println!("Testing design on plans {:?}", allplans);
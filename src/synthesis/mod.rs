@@ -11,6 +11,8 @@ use crate::document::objects::*;
 use crate::document::topology::*;
 use crate::document::dgraph;
 use crate::document::interlocking;
+use crate::document::issues::Issue;
+use crate::document::plan::ConvertPlanErr;
 
 mod abstractdispatch;
 mod initial;
@@ -30,10 +32,11 @@ pub struct SynthesisBackground<'a> {
     pub topology :&'a Topology,
     pub plans :&'a [PlanSpec],
     pub vehicles :&'a [(usize,Vehicle)],
+    pub interlocking :&'a InterlockingSettings,
 }
 
 #[derive(Debug)]
-pub enum SynErr { Aborted }
+pub enum SynErr { Aborted, Model(Issue), Plan(ConvertPlanErr) }
 
 pub type Design = Vec<Object>;
 pub type Object = (usize,f64,Function,Option<AB>);
@@ -48,7 +51,7 @@ pub fn full_synthesis( bg :&SynthesisBackground,
     output(FullSynMsg::ModelAvailable(format!("Maximal model"), 0.0, 
                                       maximal_objects.clone())).ok_or(SynErr::Aborted)?;
 
-    let mut signal_set_iterator = reduce::reduced_signal_sets(bg, maximal_objects);
+    let mut signal_set_iterator = reduce::reduced_signal_sets(bg, maximal_objects)?;
 
     // Try all minimal signal sets
     // TODO reorg to breadth first?
@@ -89,7 +92,8 @@ pub fn full_synthesis( bg :&SynthesisBackground,
 }
 
 
-pub fn create_model(bg :&SynthesisBackground, design :&Vec<Object>) -> (Topology,dgraph::DGraph,interlocking::Interlocking) {
+pub fn create_model(bg :&SynthesisBackground, design :&Vec<Object>) 
+    -> Result<(Topology,dgraph::DGraph,interlocking::Interlocking), Issue> {
     let mut topo = (*bg.topology).clone();
     topo.trackobjects = topo.tracks.iter().map(|_| Vec::new()).collect::<Vec<_>>();
     for (obj_idx,(track_idx,pos,func,dir)) in design.iter().enumerate() {
@@ -99,12 +103,12 @@ pub fn create_model(bg :&SynthesisBackground, design :&Vec<Object>) -> (Topology
         }
     }
 
-    let dgraph = dgraph::DGraphBuilder::convert(&topo).unwrap();
-    let il = interlocking::calc(&dgraph);
+    let dgraph = dgraph::DGraphBuilder::convert(&topo)?;
+    let il = interlocking::calc(&dgraph, bg.interlocking)?;
 
     //println!("create_model interlocking");
     //for r in il.routes.iter() {
         //println!("route  {:?}", r);
     //}
-    (topo,dgraph,il)
+    Ok((topo,dgraph,il))
 }
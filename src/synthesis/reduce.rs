@@ -12,19 +12,19 @@ use crate::document::interlocking;
 use crate::document::plan;
 
 pub fn reduced_signal_sets<'a>(bg :&'a SynthesisBackground, design :Design) 
-    -> impl Iterator<Item = (Design, MultiPlan)> + 'a {
+    -> Result<impl Iterator<Item = (Design, MultiPlan)> + 'a, SynErr> {
         
-    let (topo,dgraph,il) = create_model(bg, &design);
+    let (topo,dgraph,il) = create_model(bg, &design).map_err(SynErr::Model)?;
     let inf = plan::convert_inf(&il.routes.iter()
                                 .map(|i| i.route.clone()).enumerate().collect());
-    let plans = bg.plans.iter().map(|p| plan::convert_plan(&il, bg.vehicles, p))
-        .collect::<Result<Vec<_>,_>>().unwrap();
+    let plans = bg.plans.iter().map(|p| plan::convert_plan(&dgraph, &il, bg.vehicles, p))
+        .collect::<Result<Vec<_>,_>>().map_err(SynErr::Plan)?;
 
     //println!("create optmizer");
     let mut optimizer = planner::optimize::SignalOptimizer::new(inf, plans.into());
     //println!("create optmizer ok");
 
-    Iter { bg, topo, dgraph, il, optimizer }
+    Ok(Iter { bg, topo, dgraph, il, optimizer })
 }
 
 pub struct Iter<'a> {
@@ -108,6 +108,7 @@ fn convert_signals(topo :&Topology, dgraph :&dgraph::DGraph,
                         design.push((track_idx, *pos, Function::Detector, None));
                     }
                 },
+                Function::SpeedLimit { .. } => {},
             }
         }
     }
@@ -0,0 +1,200 @@
+// Vehicle library shared between documents.
+//
+// The library is stored with `confy` next to the user configuration. Vehicles
+// are copied between the library and the documents, and compositions of
+// several library vehicles are combined into a single document vehicle.
+// Vehicles can also be exported to and imported from TOML files.
+
+use confy;
+use log::*;
+use serde::{Serialize, Deserialize};
+
+use crate::document::model::*;
+use crate::document::dynamics;
+
+const LIBRARY_NAME :&str = concat!(env!("CARGO_PKG_NAME"), "-vehicles");
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Clone, Default)]
+#[serde(default)]
+pub struct VehicleLibrary {
+    pub vehicles :Vec<Vehicle>,
+    pub compositions :Vec<Composition>,
+}
+
+/// A train made from several vehicles in the library, referenced by name.
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Clone)]
+pub struct Composition {
+    pub name :String,
+    pub units :Vec<CompositionUnit>,
+}
+
+#[derive(Serialize,Deserialize)]
+#[derive(Debug, Clone)]
+pub struct CompositionUnit {
+    pub vehicle :String,
+    pub count :usize,
+}
+
+impl VehicleLibrary {
+    pub fn load() -> Self {
+        confy::load(LIBRARY_NAME).unwrap_or_else(|e| {
+            error!("Could not load vehicle library: {}", e);
+            Default::default()
+        })
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = confy::store(LIBRARY_NAME, self.clone()) {
+            error!("Could not save vehicle library: {}", e);
+        }
+    }
+
+    pub fn from_toml(s :&str) -> Result<Self, String> {
+        toml::from_str(s).map_err(|e| format!("Could not read vehicles: {}", e))
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|e| format!("Could not write vehicles: {}", e))
+    }
+
+    /// Add a vehicle, replacing any library vehicle with the same name.
+    pub fn add(&mut self, vehicle :Vehicle) {
+        match self.vehicles.iter_mut().find(|v| v.name == vehicle.name) {
+            Some(v) => { *v = vehicle; },
+            None => { self.vehicles.push(vehicle); },
+        }
+    }
+
+    /// Combine the units of a composition into one vehicle. The train is as
+    /// long as its units together and as slow as its slowest unit. Forces and
+    /// masses are added when all units have a physical model, and otherwise
+    /// the lowest acceleration and braking rates are used.
+    pub fn compose(&self, composition :&Composition) -> Result<Vehicle, String> {
+        let mut units = Vec::new();
+        for unit in composition.units.iter() {
+            let vehicle = self.vehicles.iter().find(|v| v.name == unit.vehicle)
+                .ok_or(format!("Unknown vehicle {:?} in composition {:?}", unit.vehicle, composition.name))?;
+            for _ in 0..unit.count { units.push(vehicle); }
+        }
+        if units.is_empty() {
+            return Err(format!("Composition {:?} has no units", composition.name));
+        }
+
+        let min = |f :fn(&Vehicle) -> f32| units.iter().map(|v| f(v)).fold(std::f32::INFINITY, f32::min);
+        let combined = units.iter().map(|v| v.dynamics.as_ref()).collect::<Option<Vec<_>>>()
+            .map(|ds| combine_dynamics(&ds));
+
+        Ok(Vehicle {
+            name: composition.name.clone(),
+            length: units.iter().map(|v| v.length).sum(),
+            max_acc: match &combined {
+                Some(d) => (dynamics::acceleration(d, 0.0, 0.0) as f32).max(0.01),
+                None => min(|v| v.max_acc),
+            },
+            max_brk: min(|v| v.max_brk),
+            max_vel: min(|v| v.max_vel),
+            dynamics: combined,
+        })
+    }
+}
+
+fn combine_dynamics(units :&[&VehicleDynamics]) -> VehicleDynamics {
+    let mass :f32 = units.iter().map(|d| d.mass).sum();
+    let rotating_mass = units.iter().map(|d| d.mass * d.rotating_mass).sum::<f32>() / mass.max(1e-3);
+
+    // Sum the curves at each unit's points. Units without traction add no force,
+    // but a brake curve is only used if every unit has one.
+    let sum_curves = |curve :fn(&VehicleDynamics) -> &Vec<(f32,f32)>, all :bool| -> Vec<(f32,f32)> {
+        if units.iter().all(|d| curve(d).is_empty()) { return Vec::new(); }
+        if all && units.iter().any(|d| curve(d).is_empty()) { return Vec::new(); }
+        let mut speeds = units.iter().flat_map(|d| curve(d).iter().map(|(v,_)| *v)).collect::<Vec<_>>();
+        speeds.sort_by(|a,b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        speeds.dedup();
+        speeds.into_iter().map(|v| (v, units.iter()
+                .map(|d| dynamics::interpolate(curve(d), v as f64) as f32).sum())).collect()
+    };
+
+    VehicleDynamics {
+        mass,
+        rotating_mass,
+        davis: (units.iter().map(|d| d.davis.0).sum(),
+                units.iter().map(|d| d.davis.1).sum(),
+                units.iter().map(|d| d.davis.2).sum()),
+        tractive_effort: sum_curves(|d| &d.tractive_effort, false),
+        brake_effort: sum_curves(|d| &d.brake_effort, true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vehicle(name :&str, length :f32, max_vel :f32, dynamics :Option<VehicleDynamics>) -> Vehicle {
+        Vehicle { name: name.to_string(), length, max_acc: 0.5, max_brk: 0.6, max_vel, dynamics }
+    }
+
+    fn library() -> VehicleLibrary {
+        let loco = VehicleDynamics {
+            mass: 80.0,
+            rotating_mass: 1.2,
+            davis: (2.0, 0.02, 0.005),
+            tractive_effort: vec![(0.0, 200.0), (20.0, 100.0)],
+            brake_effort: vec![(0.0, 100.0)],
+        };
+        let wagon = VehicleDynamics {
+            mass: 40.0,
+            rotating_mass: 1.0,
+            davis: (1.0, 0.01, 0.001),
+            tractive_effort: vec![],
+            brake_effort: vec![],
+        };
+        VehicleLibrary {
+            vehicles: vec![
+                vehicle("loco", 20.0, 40.0, Some(loco)),
+                vehicle("wagon", 15.0, 30.0, Some(wagon)),
+                Vehicle { max_acc: 0.3, max_brk: 0.4, ..vehicle("coach", 25.0, 50.0, None) },
+            ],
+            compositions: vec![],
+        }
+    }
+
+    fn composition(units :&[(&str,usize)]) -> Composition {
+        Composition { name: format!("train"), units: units.iter()
+            .map(|(v,n)| CompositionUnit { vehicle: v.to_string(), count: *n }).collect() }
+    }
+
+    #[test]
+    pub fn compose_dynamics() {
+        let train = library().compose(&composition(&[("loco",1),("wagon",2)])).unwrap();
+        assert_eq!(train.name, "train");
+        assert_eq!(train.length, 50.0);
+        assert_eq!(train.max_vel, 30.0);
+        let d = train.dynamics.as_ref().unwrap();
+        assert_eq!(d.mass, 160.0);
+        assert!((d.rotating_mass - 1.1).abs() < 1e-6);
+        assert_eq!(d.davis.0, 4.0);
+        assert_eq!(d.tractive_effort, vec![(0.0, 200.0), (20.0, 100.0)]);
+        // Not every unit has a brake curve.
+        assert!(d.brake_effort.is_empty());
+        assert!((train.max_acc as f64 - dynamics::acceleration(d, 0.0, 0.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    pub fn compose_without_dynamics() {
+        let train = library().compose(&composition(&[("loco",1),("coach",3)])).unwrap();
+        assert_eq!(train.length, 95.0);
+        assert_eq!(train.max_vel, 40.0);
+        assert!(train.dynamics.is_none());
+        assert_eq!(train.max_acc, 0.3);
+        assert_eq!(train.max_brk, 0.4);
+    }
+
+    #[test]
+    pub fn compose_errors() {
+        assert!(library().compose(&composition(&[("loco",1),("railbus",1)])).is_err());
+        assert!(library().compose(&composition(&[])).is_err());
+        assert!(library().compose(&composition(&[("loco",0)])).is_err());
+    }
+}